@@ -0,0 +1,162 @@
+//! Exposes the `AsyncPool` type, the threads driving asynchronous handlers.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use futures::sync::{mpsc, oneshot};
+use futures::{Future, IntoFuture, Stream};
+use num_cpus;
+use tokio::runtime::current_thread;
+
+// A job sent to a worker, creating the future the worker drives.
+type Job = Box<dyn FnOnce() -> Box<dyn Future<Item = (), Error = ()>> + Send>;
+
+/// A pool of threads driving the futures of asynchronous handlers.
+///
+/// The futures of an `AsyncHandler` own a `Request`, so they aren't `Send`
+/// and can't be driven by the multi-threaded event loop serving the
+/// connections. Each thread of the pool runs an event loop of its own
+/// instead, and each future is driven on the thread it was created on.
+/// Requests are handed to the threads in turn.
+///
+/// The threads are started when the first request is spawned, and stop
+/// once every clone of the pool is dropped and their futures completed.
+#[derive(Clone)]
+pub struct AsyncPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    size: usize,
+    // Empty until the first job is spawned
+    workers: Mutex<Vec<mpsc::UnboundedSender<Job>>>,
+    next: AtomicUsize,
+}
+
+impl AsyncPool {
+    /// Create a pool of `size` threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn new(size: usize) -> AsyncPool {
+        assert!(size > 0, "An AsyncPool needs at least one thread.");
+        AsyncPool {
+            inner: Arc::new(Inner {
+                size,
+                workers: Mutex::new(Vec::new()),
+                next: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Create a pool with one thread per CPU.
+    pub fn new_num_cpus() -> AsyncPool {
+        AsyncPool::new(num_cpus::get())
+    }
+
+    /// The number of threads of the pool.
+    pub fn size(&self) -> usize {
+        self.inner.size
+    }
+
+    /// Run the future created by `f` on one of the threads of the pool.
+    ///
+    /// The returned future completes with the result of the spawned one, or
+    /// with `Canceled` if it panicked.
+    pub fn spawn_fn<F, R>(&self, f: F) -> oneshot::Receiver<Result<R::Item, R::Error>>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: IntoFuture + 'static,
+        R::Item: Send + 'static,
+        R::Error: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            Box::new(f().into_future().then(move |result| {
+                // The receiver may be gone, no one is waiting for the result then.
+                let _ = tx.send(result);
+                Ok(())
+            }))
+        });
+
+        let index = self.inner.next.fetch_add(1, Ordering::Relaxed) % self.inner.size;
+        let mut workers = self.inner.workers.lock().unwrap();
+        if workers.is_empty() {
+            *workers = (0..self.inner.size).map(start_worker).collect();
+        }
+        // Sending only fails if the worker couldn't start its event loop,
+        // which drops the job and cancels `rx`.
+        let _ = workers[index].unbounded_send(job);
+        rx
+    }
+}
+
+impl fmt::Debug for AsyncPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncPool")
+            .field("size", &self.inner.size)
+            .finish()
+    }
+}
+
+// Start a thread running the jobs sent to the returned sender.
+fn start_worker(index: usize) -> mpsc::UnboundedSender<Job> {
+    let (tx, rx) = mpsc::unbounded::<Job>();
+    thread::Builder::new()
+        .name(format!("iron-async-{}", index))
+        .spawn(move || {
+            current_thread::run(rx.for_each(|job| {
+                // A panic cancels its own job only, not the others of the thread.
+                if let Ok(future) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                    current_thread::spawn(AssertUnwindSafe(future).catch_unwind().then(|_| Ok(())));
+                }
+                Ok(())
+            }))
+        })
+        .expect("failed to start an AsyncPool thread");
+    tx
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use futures::future;
+
+    #[test]
+    fn test_spawn_fn() {
+        let pool = AsyncPool::new(2);
+        let results = (0..4)
+            .map(|i| {
+                pool.spawn_fn(move || {
+                    // Not `Send`, but never leaves the thread it was created on
+                    let value = Rc::new(i);
+                    future::lazy(move || Ok::<_, ()>(*value * 2))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let results = results
+            .into_iter()
+            .map(|rx| rx.wait().unwrap().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, [0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_spawn_fn_panic() {
+        let pool = AsyncPool::new(1);
+        let rx = pool.spawn_fn(|| -> Result<(), ()> { panic!("boom") });
+        assert!(rx.wait().is_err());
+
+        // The thread survives
+        let rx = pool.spawn_fn(|| Ok::<_, ()>(1));
+        assert_eq!(rx.wait(), Ok(Ok(1)));
+    }
+}
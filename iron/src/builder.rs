@@ -0,0 +1,340 @@
+//! Exposes `IronBuilder`, which configures an `Iron` and validates its
+//! configuration.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_cpupool::CpuPool;
+
+use headers::{HeaderMap, HeaderName, HeaderValue};
+use {AsyncPool, Handler, Iron, Protocol, Timeouts};
+
+#[cfg(feature = "config")]
+use config::Config;
+
+// hyper refuses smaller read buffers.
+const MIN_BUF_SIZE: usize = 8192;
+
+/// A builder for `Iron`, created by `Iron::builder`.
+///
+/// Settings are validated when the `Iron` is built, instead of panicking
+/// when the server starts:
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use iron::prelude::*;
+/// use iron::StatusCode;
+///
+/// let iron = Iron::builder(|_: &mut Request| Ok(Response::with(StatusCode::OK)))
+///     .threads(8)
+///     .keep_alive(Some(Duration::from_secs(30)))
+///     .server_header("my-service")
+///     .build()
+///     .unwrap();
+/// iron.http("localhost:3000");
+/// ```
+pub struct IronBuilder<H> {
+    handler: H,
+    timeouts: Timeouts,
+    threads: Option<usize>,
+    pool: Option<CpuPool>,
+    async_pool: Option<AsyncPool>,
+    protocol: Protocol,
+    local_address: Option<SocketAddr>,
+    max_buf_size: Option<usize>,
+    default_headers: Vec<(String, String)>,
+    error: Option<ConfigError>,
+}
+
+impl<H: Handler> IronBuilder<H> {
+    /// Create a builder for an `Iron` serving `handler`, with the defaults of
+    /// `Iron::new`.
+    pub fn new(handler: H) -> IronBuilder<H> {
+        IronBuilder {
+            handler,
+            timeouts: Timeouts::default(),
+            threads: None,
+            pool: None,
+            async_pool: None,
+            protocol: Protocol::http(),
+            local_address: None,
+            max_buf_size: None,
+            default_headers: vec![],
+            error: None,
+        }
+    }
+
+    /// Set the server timeouts.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> IronBuilder<H> {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Set the keep alive timeout, or turn keep alive off with `None`.
+    ///
+    /// A zero timeout is invalid.
+    pub fn keep_alive(mut self, keep_alive: Option<Duration>) -> IronBuilder<H> {
+        self.timeouts.keep_alive = keep_alive;
+        self
+    }
+
+    /// Run synchronous handlers on a new pool of `threads` threads.
+    ///
+    /// Defaults to one thread per CPU. Zero threads are invalid.
+    pub fn threads(mut self, threads: usize) -> IronBuilder<H> {
+        self.threads = Some(threads);
+        self.pool = None;
+        self
+    }
+
+    /// Run synchronous handlers on an existing pool.
+    pub fn pool(mut self, pool: CpuPool) -> IronBuilder<H> {
+        self.pool = Some(pool);
+        self.threads = None;
+        self
+    }
+
+    /// Drive asynchronous handlers on an existing pool.
+    ///
+    /// Defaults to a pool with one thread per CPU.
+    pub fn async_pool(mut self, async_pool: AsyncPool) -> IronBuilder<H> {
+        self.async_pool = Some(async_pool);
+        self
+    }
+
+    /// Set the protocol of incoming requests.
+    ///
+    /// `Iron` doesn't terminate TLS itself, so `Protocol::https` is for
+    /// servers behind a proxy which does.
+    pub fn protocol(mut self, protocol: Protocol) -> IronBuilder<H> {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Set the address used as the host of requests which don't give one.
+    pub fn local_address(mut self, addr: SocketAddr) -> IronBuilder<H> {
+        self.local_address = Some(addr);
+        self
+    }
+
+    /// Limit the buffer used to read requests, and so the size of request
+    /// heads.
+    ///
+    /// Sizes below 8192 bytes are invalid.
+    pub fn max_buf_size(mut self, max_buf_size: usize) -> IronBuilder<H> {
+        self.max_buf_size = Some(max_buf_size);
+        self
+    }
+
+    /// Add a header to every response which doesn't set it.
+    ///
+    /// Invalid names and values are reported by `build`.
+    pub fn default_header(mut self, name: &str, value: &str) -> IronBuilder<H> {
+        self.default_headers
+            .push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Send a `Server` header with every response which doesn't set one.
+    ///
+    /// The `Date` header is always sent by hyper.
+    pub fn server_header(self, server: &str) -> IronBuilder<H> {
+        self.default_header("server", server)
+    }
+
+    /// Apply the settings of a `Config`, on top of the current ones.
+    #[cfg(feature = "config")]
+    pub fn config(mut self, config: &Config) -> IronBuilder<H> {
+        if let Some(addr) = config.address {
+            self = self.local_address(addr);
+        }
+        if let Some(ref protocol) = config.protocol {
+            match protocol.as_str() {
+                "http" => self = self.protocol(Protocol::http()),
+                "https" => self = self.protocol(Protocol::https()),
+                _ => {
+                    self.fail(ConfigError::Protocol(protocol.clone()));
+                }
+            }
+        }
+        if let Some(secs) = config.keep_alive {
+            // Keep alive is turned off by a zero timeout in configuration files.
+            self = self.keep_alive(if secs == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(secs))
+            });
+        }
+        if let Some(threads) = config.threads {
+            self = self.threads(threads);
+        }
+        if let Some(max_buf_size) = config.max_buf_size {
+            self = self.max_buf_size(max_buf_size);
+        }
+        for (name, value) in &config.headers {
+            self = self.default_header(name, value);
+        }
+        self
+    }
+
+    /// Validate the configuration and create the `Iron`.
+    pub fn build(self) -> Result<Iron<H>, ConfigError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if self.timeouts.keep_alive == Some(Duration::from_secs(0)) {
+            return Err(ConfigError::KeepAlive);
+        }
+        if self.threads == Some(0) {
+            return Err(ConfigError::Threads);
+        }
+        match self.max_buf_size {
+            Some(size) if size < MIN_BUF_SIZE => return Err(ConfigError::MaxBufSize(size)),
+            _ => {}
+        }
+
+        let mut default_headers = HeaderMap::new();
+        for (name, value) in self.default_headers {
+            let invalid = || ConfigError::Header(name.clone());
+            let header = HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?;
+            let value = HeaderValue::from_str(&value).map_err(|_| invalid())?;
+            default_headers.append(header, value);
+        }
+
+        let pool = match (self.pool, self.threads) {
+            (Some(pool), _) => pool,
+            (None, Some(threads)) => CpuPool::new(threads),
+            (None, None) => CpuPool::new_num_cpus(),
+        };
+
+        Ok(Iron {
+            handler: Arc::new(self.handler),
+            timeouts: self.timeouts,
+            pool,
+            async_pool: self.async_pool.unwrap_or_else(AsyncPool::new_num_cpus),
+            protocol: self.protocol,
+            local_address: self.local_address,
+            panic_hook: None,
+            panic_response: None,
+            bad_request_handler: None,
+            max_buf_size: self.max_buf_size,
+            default_headers,
+        })
+    }
+
+    // Only the first error is reported.
+    #[cfg(feature = "config")]
+    fn fail(&mut self, err: ConfigError) {
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
+    }
+}
+
+/// An invalid server configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A keep alive timeout of zero.
+    KeepAlive,
+    /// A thread pool without threads.
+    Threads,
+    /// A request buffer smaller than 8192 bytes.
+    MaxBufSize(usize),
+    /// A default header with an invalid name or value.
+    Header(String),
+    /// A protocol other than `http` or `https`.
+    Protocol(String),
+    /// A configuration which couldn't be parsed.
+    Parse(String),
+    /// A configuration file which couldn't be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::KeepAlive => f.write_str("Keep alive timeout must not be zero"),
+            ConfigError::Threads => f.write_str("Thread pool must have at least one thread"),
+            ConfigError::MaxBufSize(size) => write!(
+                f,
+                "Max buffer size must be at least {} bytes, not {}",
+                MIN_BUF_SIZE, size
+            ),
+            ConfigError::Header(ref name) => write!(f, "Invalid default header `{}`", name),
+            ConfigError::Protocol(ref name) => write!(f, "Unknown protocol `{}`", name),
+            ConfigError::Parse(ref err) => write!(f, "Invalid configuration: {}", err),
+            ConfigError::Io(ref err) => write!(f, "Couldn't read configuration: {}", err),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ConfigError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use {Request, Response, StatusCode};
+
+    fn handler(_: &mut Request) -> ::IronResult<Response> {
+        Ok(Response::with(StatusCode::OK))
+    }
+
+    #[test]
+    fn test_build() {
+        let iron = Iron::builder(handler)
+            .keep_alive(None)
+            .threads(2)
+            .protocol(Protocol::https())
+            .max_buf_size(16384)
+            .server_header("iron")
+            .default_header("x-frame-options", "DENY")
+            .build()
+            .unwrap();
+        assert_eq!(iron.timeouts.keep_alive, None);
+        assert_eq!(iron.protocol.name(), "https");
+        assert_eq!(iron.max_buf_size, Some(16384));
+        assert_eq!(iron.default_headers["server"], "iron");
+        assert_eq!(iron.default_headers["x-frame-options"], "DENY");
+    }
+
+    #[test]
+    fn test_validation() {
+        let err = |builder: IronBuilder<fn(&mut Request) -> ::IronResult<Response>>| {
+            builder.build().err().unwrap().to_string()
+        };
+        let builder = || Iron::builder(handler as fn(&mut Request) -> _);
+
+        assert_eq!(
+            err(builder().keep_alive(Some(Duration::from_secs(0)))),
+            "Keep alive timeout must not be zero"
+        );
+        assert_eq!(
+            err(builder().threads(0)),
+            "Thread pool must have at least one thread"
+        );
+        assert_eq!(
+            err(builder().max_buf_size(1024)),
+            "Max buffer size must be at least 8192 bytes, not 1024"
+        );
+        assert_eq!(
+            err(builder().default_header("bad name", "x")),
+            "Invalid default header `bad name`"
+        );
+        assert_eq!(
+            err(builder().server_header("line\nbreak")),
+            "Invalid default header `server`"
+        );
+    }
+}
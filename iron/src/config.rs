@@ -0,0 +1,181 @@
+//! Server configuration loaded from TOML files and the environment.
+//!
+//! Enabled by the `config` feature. A `Config` is applied to an
+//! `IronBuilder`, which validates it:
+//!
+//! ```no_run
+//! use iron::config::Config;
+//! use iron::prelude::*;
+//! use iron::StatusCode;
+//!
+//! let config = Config::from_file("server.toml")
+//!     .and_then(|config| config.merge_env("SERVER"))
+//!     .unwrap();
+//!
+//! let iron = Iron::builder(|_: &mut Request| Ok(Response::with(StatusCode::OK)))
+//!     .config(&config)
+//!     .build()
+//!     .unwrap();
+//! iron.http(config.address.unwrap());
+//! ```
+//!
+//! A configuration file sets any of the following:
+//!
+//! ```toml
+//! address = "0.0.0.0:8080"
+//! protocol = "https"
+//! keep_alive = 30 # seconds, 0 turns keep alive off
+//! threads = 16
+//! max_buf_size = 65536
+//!
+//! [headers]
+//! server = "my-service"
+//! ```
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+use toml;
+
+use ConfigError;
+
+/// The configuration of a server.
+///
+/// Unset fields keep the settings of the `IronBuilder` it is applied to.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The address to listen on, also used as the host of requests which
+    /// don't give one.
+    pub address: Option<SocketAddr>,
+    /// The protocol of incoming requests, `http` or `https`.
+    pub protocol: Option<String>,
+    /// The keep alive timeout in seconds, or 0 to turn keep alive off.
+    pub keep_alive: Option<u64>,
+    /// The number of threads running synchronous handlers.
+    pub threads: Option<usize>,
+    /// The largest buffer used to read requests.
+    pub max_buf_size: Option<usize>,
+    /// Headers added to every response which doesn't set them.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Parse a configuration from TOML.
+    pub fn from_toml(input: &str) -> Result<Config, ConfigError> {
+        toml::from_str(input).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+
+    /// Read a configuration from a TOML file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let input = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Config::from_toml(&input)
+    }
+
+    /// Override settings from environment variables named after the fields,
+    /// such as `SERVER_ADDRESS` and `SERVER_THREADS` for the prefix `SERVER`.
+    ///
+    /// Headers can't be set from the environment.
+    pub fn merge_env(mut self, prefix: &str) -> Result<Config, ConfigError> {
+        let var = |name: &str| env::var(format!("{}_{}", prefix, name)).ok();
+
+        if let Some(address) = var("ADDRESS") {
+            self.address = Some(parse("ADDRESS", &address)?);
+        }
+        if let Some(protocol) = var("PROTOCOL") {
+            self.protocol = Some(protocol);
+        }
+        if let Some(keep_alive) = var("KEEP_ALIVE") {
+            self.keep_alive = Some(parse("KEEP_ALIVE", &keep_alive)?);
+        }
+        if let Some(threads) = var("THREADS") {
+            self.threads = Some(parse("THREADS", &threads)?);
+        }
+        if let Some(max_buf_size) = var("MAX_BUF_SIZE") {
+            self.max_buf_size = Some(parse("MAX_BUF_SIZE", &max_buf_size)?);
+        }
+        Ok(self)
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::Parse(format!("invalid value `{}` for {}", value, name)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+
+    use {Iron, Request, Response, StatusCode};
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(
+            r#"
+            address = "127.0.0.1:8080"
+            keep_alive = 0
+            threads = 4
+
+            [headers]
+            server = "iron"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.address, Some(([127, 0, 0, 1], 8080).into()));
+        assert_eq!(config.keep_alive, Some(0));
+        assert_eq!(config.threads, Some(4));
+        assert_eq!(config.headers["server"], "iron");
+
+        let err = Config::from_toml("thread = 4").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid configuration: unknown field `thread`"));
+    }
+
+    #[test]
+    fn test_merge_env() {
+        env::set_var("IRON_CONFIG_TEST_THREADS", "2");
+        env::set_var("IRON_CONFIG_TEST_KEEP_ALIVE", "10");
+        let config = Config::from_toml("threads = 4")
+            .unwrap()
+            .merge_env("IRON_CONFIG_TEST")
+            .unwrap();
+        assert_eq!(config.threads, Some(2));
+        assert_eq!(config.keep_alive, Some(10));
+
+        env::set_var("IRON_CONFIG_TEST_BAD_THREADS", "many");
+        let err = Config::default()
+            .merge_env("IRON_CONFIG_TEST_BAD")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid configuration: invalid value `many` for THREADS"
+        );
+    }
+
+    #[test]
+    fn test_builder_config() {
+        let handler = |_: &mut Request| Ok(Response::with(StatusCode::OK));
+
+        let config = Config::from_toml("keep_alive = 30\nprotocol = \"https\"").unwrap();
+        let iron = Iron::builder(handler).config(&config).build().unwrap();
+        assert_eq!(iron.timeouts.keep_alive, Some(Duration::from_secs(30)));
+        assert_eq!(iron.protocol.name(), "https");
+
+        let config = Config::from_toml("protocol = \"gopher\"").unwrap();
+        let err = Iron::builder(handler)
+            .config(&config)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "Unknown protocol `gopher`");
+    }
+}
@@ -1,7 +1,10 @@
 use std::fmt;
 
-use modifier::Modifier;
-use Response;
+use mime::{self, Mime};
+use modifier::{Modifier, Set};
+use modifiers::escape_html;
+use typemap::Key;
+use {AfterMiddleware, IronResult, Request, Response, StatusCode};
 
 pub use hyper::error::Result as HttpResult;
 pub use hyper::Error as HttpError;
@@ -42,6 +45,87 @@ impl IronError {
             response: Response::with(m),
         }
     }
+
+    /// Layer `context` on top of the error, describing what was being done
+    /// when it occured.
+    ///
+    /// The original error becomes the `source` of the new one.
+    ///
+    /// ```
+    /// # use std::io;
+    /// # use iron::prelude::*;
+    /// # use iron::StatusCode;
+    /// let refused = io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused");
+    /// let err = IronError::new(refused, StatusCode::BAD_GATEWAY).context("loading user");
+    /// assert_eq!(err.chain().to_string(), "loading user: connection refused");
+    /// ```
+    pub fn context<C: fmt::Display>(mut self, context: C) -> IronError {
+        self.error = Box::new(Context {
+            context: context.to_string(),
+            source: self.error,
+        });
+        self
+    }
+
+    /// Walk the error and its chain of sources, starting with the error
+    /// itself.
+    ///
+    /// The returned iterator displays as each error in turn, separated by
+    /// colons.
+    pub fn chain(&self) -> ErrorChain<'_> {
+        ErrorChain {
+            next: Some(&*self.error),
+        }
+    }
+}
+
+/// An error layered with context by `IronError::context`.
+#[derive(Debug)]
+pub struct Context {
+    context: String,
+    source: Box<dyn Error + Send>,
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.context)
+    }
+}
+
+impl Error for Context {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+/// An iterator over an error and its chain of sources.
+///
+/// Created by `IronError::chain`.
+#[derive(Clone)]
+pub struct ErrorChain<'a> {
+    next: Option<&'a (dyn Error + 'static)>,
+}
+
+impl<'a> Iterator for ErrorChain<'a> {
+    type Item = &'a (dyn Error + 'static);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next.take()?;
+        self.next = next.source();
+        Some(next)
+    }
+}
+
+impl<'a> fmt::Display for ErrorChain<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, err) in self.clone().enumerate() {
+            if i > 0 {
+                f.write_str(": ")?;
+            }
+            fmt::Display::fmt(err, f)?;
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Display for IronError {
@@ -59,3 +143,343 @@ impl Error for IronError {
         self.error.source()
     }
 }
+
+/// The default modifier of `itry!`, setting the status to
+/// `500 Internal Server Error`.
+///
+/// Unlike the status code itself, it marks the response as a default, which
+/// an `ErrorResponses` registry may replace based on the type of the error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultStatus;
+
+impl Key for DefaultStatus {
+    type Value = ();
+}
+
+impl Modifier<Response> for DefaultStatus {
+    fn modify(self, res: &mut Response) {
+        res.status = Some(StatusCode::INTERNAL_SERVER_ERROR);
+        res.extensions.insert::<DefaultStatus>(());
+    }
+}
+
+/// An `AfterMiddleware` choosing the response of errors by their type.
+///
+/// Errors created with the default response of `itry!` are looked up by the
+/// type of the error and each of its sources, in order. The response of the
+/// first registered mapping matching one of them replaces the default one.
+/// Errors with an explicit response are left alone.
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate iron;
+/// # use std::fmt;
+/// use iron::error::ErrorResponses;
+/// use iron::prelude::*;
+/// use iron::StatusCode;
+///
+/// #[derive(Debug)]
+/// struct Conflict(String);
+/// # impl fmt::Display for Conflict {
+/// #     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(&self.0) }
+/// # }
+/// # impl std::error::Error for Conflict {}
+///
+/// fn reserve(name: &str) -> IronResult<()> {
+///     itry!(Err(Conflict(format!("{} is taken", name))));
+///     Ok(())
+/// }
+///
+/// fn create_user(_: &mut Request) -> IronResult<Response> {
+///     reserve("admin")?;
+///     Ok(Response::with(StatusCode::CREATED))
+/// }
+///
+/// # fn main() {
+/// let mut errors = ErrorResponses::new();
+/// errors.map(|err: &Conflict| (StatusCode::CONFLICT, err.0.clone()));
+///
+/// let res = errors.response_for(&reserve("admin").unwrap_err()).unwrap();
+/// assert_eq!(res.status, Some(StatusCode::CONFLICT));
+///
+/// let mut chain = Chain::new(create_user);
+/// chain.link_after(errors);
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ErrorResponses {
+    mappings: Vec<Box<Mapping>>,
+}
+
+// Produces the response for an error, if it has the type of the mapping.
+type Mapping = dyn Fn(&(dyn Error + 'static)) -> Option<Response> + Send + Sync;
+
+impl ErrorResponses {
+    /// Create an empty registry.
+    pub fn new() -> ErrorResponses {
+        ErrorResponses::default()
+    }
+
+    /// Respond to errors of type `E` with the modifier returned by `f`.
+    pub fn map<E, F, M>(&mut self, f: F) -> &mut ErrorResponses
+    where
+        E: Error + 'static,
+        F: Fn(&E) -> M + Send + Sync + 'static,
+        M: Modifier<Response>,
+    {
+        self.mappings
+            .push(Box::new(move |err: &(dyn Error + 'static)| {
+                err.downcast_ref::<E>().map(|err| Response::with(f(err)))
+            }));
+        self
+    }
+
+    /// The response registered for an error, if any.
+    pub fn response_for(&self, err: &IronError) -> Option<Response> {
+        err.chain()
+            .filter_map(|cause| self.mappings.iter().filter_map(|m| m(cause)).next())
+            .next()
+    }
+}
+
+impl AfterMiddleware for ErrorResponses {
+    fn catch(&self, _: &mut Request, mut err: IronError) -> IronResult<Response> {
+        if err.response.extensions.contains::<DefaultStatus>() {
+            if let Some(res) = self.response_for(&err) {
+                err.response = res;
+            }
+        }
+        Err(err)
+    }
+}
+
+/// An `AfterMiddleware` rendering errors without a body as error pages.
+///
+/// In development, errors are rendered as an HTML page showing the request
+/// and the whole chain of errors. In production, they are rendered as an
+/// RFC 7807 `application/problem+json` document, which only contains the
+/// status of the response, so no internals are leaked to clients.
+///
+/// The status and any body already set on the response of an error are
+/// kept, and the error flow continues after rendering.
+///
+/// ```
+/// # use iron::prelude::*;
+/// use iron::error::ErrorPage;
+///
+/// # fn handler(_: &mut Request) -> IronResult<Response> { Ok(Response::new()) }
+/// let mut chain = Chain::new(handler);
+/// if cfg!(debug_assertions) {
+///     chain.link_after(ErrorPage::development());
+/// } else {
+///     chain.link_after(ErrorPage::production());
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorPage {
+    development: bool,
+}
+
+impl ErrorPage {
+    /// Render errors as detailed HTML pages.
+    pub fn development() -> ErrorPage {
+        ErrorPage { development: true }
+    }
+
+    /// Render errors as `application/problem+json` documents.
+    pub fn production() -> ErrorPage {
+        ErrorPage { development: false }
+    }
+
+    fn render_html(&self, req: &Request, err: &IronError, status: StatusCode) -> String {
+        let title = format!(
+            "{} {}",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or("Unknown")
+        );
+        let causes = err
+            .chain()
+            .map(|cause| format!("<li>{}</li>", escape_html(&cause.to_string())))
+            .collect::<String>();
+        format!(
+            "<!DOCTYPE html>\n\
+             <html>\n\
+             <head><title>{title}</title></head>\n\
+             <body>\n\
+             <h1>{title}</h1>\n\
+             <p>{method} {url}</p>\n\
+             <ol>{causes}</ol>\n\
+             </body>\n\
+             </html>\n",
+            title = title,
+            method = req.method,
+            url = escape_html(&req.url.to_string()),
+            causes = causes,
+        )
+    }
+
+    fn render_problem(&self, req: &Request, status: StatusCode) -> String {
+        format!(
+            "{{\"type\":\"about:blank\",\"title\":\"{}\",\"status\":{},\"instance\":\"{}\"}}",
+            escape_json(status.canonical_reason().unwrap_or("Unknown")),
+            status.as_u16(),
+            escape_json(req.url.as_ref().path()),
+        )
+    }
+}
+
+impl AfterMiddleware for ErrorPage {
+    fn catch(&self, req: &mut Request, mut err: IronError) -> IronResult<Response> {
+        if err.response.body.is_some() {
+            return Err(err);
+        }
+
+        let status = err
+            .response
+            .status
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        if self.development {
+            let page = self.render_html(req, &err, status);
+            err.response.set_mut((status, mime::TEXT_HTML_UTF_8, page));
+        } else {
+            let problem = self.render_problem(req, status);
+            let mime: Mime = "application/problem+json".parse().unwrap();
+            err.response.set_mut((status, mime, problem));
+        }
+        Err(err)
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io;
+
+    use middleware::Handler;
+    use Chain;
+
+    fn failing(_: &mut Request) -> IronResult<Response> {
+        let err = io::Error::new(io::ErrorKind::NotFound, "no row <1>");
+        Err(IronError::new(err, StatusCode::NOT_FOUND).context("loading user"))
+    }
+
+    fn body(res: Response) -> String {
+        let mut body = vec![];
+        res.body.unwrap().write_body(&mut body).unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    #[test]
+    fn test_error_chain() {
+        let err = failing(&mut Request::stub())
+            .unwrap_err()
+            .context("handling");
+        let chain = err.chain().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(chain, ["handling", "loading user", "no row <1>"]);
+        assert_eq!(err.to_string(), "handling");
+        assert_eq!(
+            err.chain().to_string(),
+            "handling: loading user: no row <1>"
+        );
+    }
+
+    #[test]
+    fn test_development_error_page() {
+        let mut chain = Chain::new(failing);
+        chain.link_after(ErrorPage::development());
+
+        let err = chain.handle(&mut Request::stub()).unwrap_err();
+        assert_eq!(err.response.status, Some(StatusCode::NOT_FOUND));
+        let page = body(err.response);
+        assert!(page.contains("<h1>404 Not Found</h1>"));
+        assert!(page.contains("<ol><li>loading user</li><li>no row &lt;1&gt;</li></ol>"));
+    }
+
+    #[test]
+    fn test_production_error_page() {
+        let mut chain = Chain::new(failing);
+        chain.link_after(ErrorPage::production());
+
+        let err = chain.handle(&mut Request::stub()).unwrap_err();
+        assert_eq!(
+            err.response.headers.get(::headers::CONTENT_TYPE).unwrap(),
+            "application/problem+json"
+        );
+        assert_eq!(
+            body(err.response),
+            r#"{"type":"about:blank","title":"Not Found","status":404,"instance":"/"}"#
+        );
+        assert_eq!(escape_json("a\"b\\\n"), r#"a\"b\\\u000a"#);
+    }
+
+    #[test]
+    fn test_error_responses() {
+        #[derive(Debug)]
+        struct Conflict;
+        impl fmt::Display for Conflict {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("conflict")
+            }
+        }
+        impl Error for Conflict {}
+
+        let mut errors = ErrorResponses::new();
+        errors
+            .map(|_: &Conflict| StatusCode::CONFLICT)
+            .map(|err: &io::Error| match err.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                _ => StatusCode::SERVICE_UNAVAILABLE,
+            });
+        let mut chain = Chain::new(|req: &mut Request| -> IronResult<Response> {
+            match req.url.path()[0] {
+                "conflict" => Err(IronError::new(Conflict, DefaultStatus)),
+                "explicit" => Err(IronError::new(Conflict, StatusCode::UNPROCESSABLE_ENTITY)),
+                "missing" => {
+                    let err = io::Error::new(io::ErrorKind::NotFound, "missing");
+                    Err(IronError::new(err, DefaultStatus).context("loading"))
+                }
+                _ => {
+                    let err = io::Error::new(io::ErrorKind::Other, "unmapped");
+                    Err(IronError::new(err, DefaultStatus))
+                }
+            }
+        });
+        chain.link_after(errors);
+
+        let status = |path: &str| {
+            let mut req = Request::stub();
+            req.url = ::Url::parse(&format!("http://localhost/{}", path)).unwrap();
+            chain.handle(&mut req).unwrap_err().response.status.unwrap()
+        };
+        assert_eq!(status("conflict"), StatusCode::CONFLICT);
+        assert_eq!(status("explicit"), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(status("missing"), StatusCode::NOT_FOUND);
+        assert_eq!(status("other"), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_error_page_keeps_body() {
+        let mut chain = Chain::new(|_: &mut Request| -> IronResult<Response> {
+            let err = io::Error::new(io::ErrorKind::Other, "gone");
+            Err(IronError::new(err, (StatusCode::GONE, "custom")))
+        });
+        chain.link_after(ErrorPage::production());
+
+        let err = chain.handle(&mut Request::stub()).unwrap_err();
+        assert_eq!(body(err.response), "custom");
+    }
+}
@@ -0,0 +1,551 @@
+//! HTTP headers.
+//!
+//! This re-exports hyper's raw `HeaderMap` and header names, and adds the
+//! `TypedHeader` trait for headers which can be parsed into Rust values.
+//!
+//! Typed headers are read and written through `Request::typed_header`,
+//! `Response::typed_header` and `set_typed`, or set with the
+//! `modifiers::Typed` modifier:
+//!
+//! ```
+//! # use iron::prelude::*;
+//! # use iron::StatusCode;
+//! use iron::headers::{CacheControl, CacheDirective, ContentLength};
+//! use iron::modifiers::Typed;
+//!
+//! let mut res = Response::with((
+//!     StatusCode::OK,
+//!     Typed(CacheControl(vec![CacheDirective::NoCache])),
+//! ));
+//! res.set_typed(ContentLength(0)).unwrap();
+//!
+//! assert_eq!(res.typed_header::<ContentLength>(), Some(ContentLength(0)));
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64;
+use mime::Mime;
+
+use Method;
+
+pub use hyper::header::*;
+
+/// A header which can be parsed from and turned into raw header values.
+pub trait TypedHeader: Sized {
+    /// The name of this header.
+    fn header_name() -> HeaderName;
+
+    /// Parse the header from all of its values, in order.
+    ///
+    /// Returns `None` if the values are not valid for this header.
+    fn decode<'a, I>(values: I) -> Option<Self>
+    where
+        I: Iterator<Item = &'a HeaderValue>;
+
+    /// Produce the raw value of this header.
+    ///
+    /// Fails if the header holds a string which isn't a valid header value,
+    /// such as one containing a line break.
+    fn encode(&self) -> Result<HeaderValue, InvalidHeaderValue>;
+}
+
+/// Read a typed header from a `HeaderMap`.
+///
+/// Returns `None` if the header is missing or invalid.
+pub fn get_typed<T: TypedHeader>(headers: &HeaderMap) -> Option<T> {
+    let name = T::header_name();
+    if !headers.contains_key(&name) {
+        return None;
+    }
+    T::decode(headers.get_all(name).iter())
+}
+
+/// Set a typed header on a `HeaderMap`, replacing any previous values.
+///
+/// The headers are left unchanged if the header can't be encoded.
+pub fn set_typed<T: TypedHeader>(
+    headers: &mut HeaderMap,
+    header: T,
+) -> Result<(), InvalidHeaderValue> {
+    headers.insert(T::header_name(), header.encode()?);
+    Ok(())
+}
+
+// Joins the values of a header which is a comma-separated list.
+fn list_items<'a, I>(values: I) -> Option<Vec<&'a str>>
+where
+    I: Iterator<Item = &'a HeaderValue>,
+{
+    let mut items = vec![];
+    for value in values {
+        items.extend(
+            split_list(value.to_str().ok()?)
+                .into_iter()
+                .map(|item| item.trim())
+                .filter(|item| !item.is_empty()),
+        );
+    }
+    Some(items)
+}
+
+// Splits a comma-separated list, except at the commas of quoted strings.
+fn split_list(value: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == ',' && !quoted {
+            items.push(&value[start..i]);
+            start = i + 1;
+        }
+    }
+    items.push(&value[start..]);
+    items
+}
+
+// Writes `value` as a token, or as a quoted string if it isn't one.
+fn write_token_or_quoted(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    let is_tchar = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if !value.is_empty() && value.chars().all(is_tchar) {
+        return f.write_str(value);
+    }
+
+    f.write_str("\"")?;
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            f.write_str("\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    f.write_str("\"")
+}
+
+// The value of a token or quoted string.
+fn unquote(value: &str) -> String {
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return value.to_owned();
+    }
+
+    let mut unquoted = String::new();
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+// Takes the value of a header which may only appear once.
+fn single<'a, I>(mut values: I) -> Option<&'a str>
+where
+    I: Iterator<Item = &'a HeaderValue>,
+{
+    let value = values.next()?.to_str().ok()?;
+    match values.next() {
+        Some(_) => None,
+        None => Some(value.trim()),
+    }
+}
+
+fn to_value<T: fmt::Display>(value: T) -> Result<HeaderValue, InvalidHeaderValue> {
+    HeaderValue::from_str(&value.to_string())
+}
+
+/// The `Content-Type` header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentType(pub Mime);
+
+impl TypedHeader for ContentType {
+    fn header_name() -> HeaderName {
+        CONTENT_TYPE
+    }
+
+    fn decode<'a, I>(values: I) -> Option<ContentType>
+    where
+        I: Iterator<Item = &'a HeaderValue>,
+    {
+        single(values)?.parse().ok().map(ContentType)
+    }
+
+    fn encode(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        to_value(&self.0)
+    }
+}
+
+/// The `Content-Length` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContentLength(pub u64);
+
+impl TypedHeader for ContentLength {
+    fn header_name() -> HeaderName {
+        CONTENT_LENGTH
+    }
+
+    fn decode<'a, I>(values: I) -> Option<ContentLength>
+    where
+        I: Iterator<Item = &'a HeaderValue>,
+    {
+        single(values)?.parse().ok().map(ContentLength)
+    }
+
+    fn encode(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        Ok(self.0.into())
+    }
+}
+
+/// The `Location` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location(pub String);
+
+impl TypedHeader for Location {
+    fn header_name() -> HeaderName {
+        LOCATION
+    }
+
+    fn decode<'a, I>(values: I) -> Option<Location>
+    where
+        I: Iterator<Item = &'a HeaderValue>,
+    {
+        single(values).map(|location| Location(location.to_owned()))
+    }
+
+    fn encode(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        to_value(&self.0)
+    }
+}
+
+/// The `User-Agent` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserAgent(pub String);
+
+impl TypedHeader for UserAgent {
+    fn header_name() -> HeaderName {
+        USER_AGENT
+    }
+
+    fn decode<'a, I>(values: I) -> Option<UserAgent>
+    where
+        I: Iterator<Item = &'a HeaderValue>,
+    {
+        single(values).map(|agent| UserAgent(agent.to_owned()))
+    }
+
+    fn encode(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        to_value(&self.0)
+    }
+}
+
+/// The `Allow` header, listing the methods supported by a resource.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Allow(pub Vec<Method>);
+
+impl TypedHeader for Allow {
+    fn header_name() -> HeaderName {
+        ALLOW
+    }
+
+    fn decode<'a, I>(values: I) -> Option<Allow>
+    where
+        I: Iterator<Item = &'a HeaderValue>,
+    {
+        list_items(values)?
+            .into_iter()
+            .map(|method| method.parse().ok())
+            .collect::<Option<Vec<_>>>()
+            .map(Allow)
+    }
+
+    fn encode(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        let methods: Vec<&str> = self.0.iter().map(|method| method.as_str()).collect();
+        to_value(methods.join(", "))
+    }
+}
+
+/// The `Authorization` header.
+///
+/// Only the `Basic` and `Bearer` schemes are supported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Authorization {
+    /// Credentials of the `Basic` scheme.
+    Basic {
+        /// The user name.
+        username: String,
+        /// The password, if one was given.
+        password: Option<String>,
+    },
+    /// A token of the `Bearer` scheme.
+    Bearer(String),
+}
+
+impl TypedHeader for Authorization {
+    fn header_name() -> HeaderName {
+        AUTHORIZATION
+    }
+
+    fn decode<'a, I>(values: I) -> Option<Authorization>
+    where
+        I: Iterator<Item = &'a HeaderValue>,
+    {
+        let value = single(values)?;
+        let mut parts = value.splitn(2, ' ');
+        let scheme = parts.next()?;
+        let credentials = parts.next()?.trim();
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(base64::decode(credentials).ok()?).ok()?;
+            let mut parts = decoded.splitn(2, ':');
+            Some(Authorization::Basic {
+                username: parts.next()?.to_owned(),
+                password: parts.next().map(|password| password.to_owned()),
+            })
+        } else if scheme.eq_ignore_ascii_case("bearer") {
+            Some(Authorization::Bearer(credentials.to_owned()))
+        } else {
+            None
+        }
+    }
+
+    fn encode(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        match *self {
+            Authorization::Basic {
+                ref username,
+                ref password,
+            } => {
+                let credentials = match *password {
+                    Some(ref password) => format!("{}:{}", username, password),
+                    None => format!("{}:", username),
+                };
+                to_value(format!("Basic {}", base64::encode(&credentials)))
+            }
+            Authorization::Bearer(ref token) => to_value(format!("Bearer {}", token)),
+        }
+    }
+}
+
+/// A directive of the `Cache-Control` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CacheDirective {
+    /// `no-cache`
+    NoCache,
+    /// `no-store`
+    NoStore,
+    /// `no-transform`
+    NoTransform,
+    /// `only-if-cached`
+    OnlyIfCached,
+    /// `max-age=seconds`
+    MaxAge(u32),
+    /// `max-stale=seconds`
+    MaxStale(u32),
+    /// `min-fresh=seconds`
+    MinFresh(u32),
+    /// `must-revalidate`
+    MustRevalidate,
+    /// `public`
+    Public,
+    /// `private`
+    Private,
+    /// `proxy-revalidate`
+    ProxyRevalidate,
+    /// `s-maxage=seconds`
+    SMaxAge(u32),
+    /// Any other directive, with its optional argument.
+    Extension(String, Option<String>),
+}
+
+impl fmt::Display for CacheDirective {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::CacheDirective::*;
+
+        match *self {
+            NoCache => f.write_str("no-cache"),
+            NoStore => f.write_str("no-store"),
+            NoTransform => f.write_str("no-transform"),
+            OnlyIfCached => f.write_str("only-if-cached"),
+            MaxAge(secs) => write!(f, "max-age={}", secs),
+            MaxStale(secs) => write!(f, "max-stale={}", secs),
+            MinFresh(secs) => write!(f, "min-fresh={}", secs),
+            MustRevalidate => f.write_str("must-revalidate"),
+            Public => f.write_str("public"),
+            Private => f.write_str("private"),
+            ProxyRevalidate => f.write_str("proxy-revalidate"),
+            SMaxAge(secs) => write!(f, "s-maxage={}", secs),
+            Extension(ref name, None) => f.write_str(name),
+            Extension(ref name, Some(ref arg)) => {
+                write!(f, "{}=", name)?;
+                write_token_or_quoted(f, arg)
+            }
+        }
+    }
+}
+
+impl FromStr for CacheDirective {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<CacheDirective, ()> {
+        use self::CacheDirective::*;
+
+        let mut parts = s.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let arg = parts.next().map(|arg| unquote(arg.trim()));
+        let secs = || arg.as_ref().and_then(|arg| arg.parse().ok()).ok_or(());
+
+        // Directives with an unexpected argument, like the field names of
+        // `private="Set-Cookie"`, are kept as extensions.
+        Ok(match &*name {
+            "no-cache" if arg.is_none() => NoCache,
+            "no-store" if arg.is_none() => NoStore,
+            "no-transform" if arg.is_none() => NoTransform,
+            "only-if-cached" if arg.is_none() => OnlyIfCached,
+            "max-age" => MaxAge(secs()?),
+            "max-stale" => MaxStale(secs()?),
+            "min-fresh" => MinFresh(secs()?),
+            "must-revalidate" if arg.is_none() => MustRevalidate,
+            "public" if arg.is_none() => Public,
+            "private" if arg.is_none() => Private,
+            "proxy-revalidate" if arg.is_none() => ProxyRevalidate,
+            "s-maxage" => SMaxAge(secs()?),
+            "" => return Err(()),
+            _ => Extension(name, arg),
+        })
+    }
+}
+
+/// The `Cache-Control` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheControl(pub Vec<CacheDirective>);
+
+impl TypedHeader for CacheControl {
+    fn header_name() -> HeaderName {
+        CACHE_CONTROL
+    }
+
+    fn decode<'a, I>(values: I) -> Option<CacheControl>
+    where
+        I: Iterator<Item = &'a HeaderValue>,
+    {
+        list_items(values)?
+            .into_iter()
+            .map(|directive| directive.parse().ok())
+            .collect::<Option<Vec<_>>>()
+            .map(CacheControl)
+    }
+
+    fn encode(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        let directives: Vec<String> = self.0.iter().map(|d| d.to_string()).collect();
+        to_value(directives.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use mime;
+
+    fn roundtrip<T: TypedHeader + PartialEq + fmt::Debug>(header: T, raw: &str) {
+        assert_eq!(header.encode().unwrap(), raw);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(T::header_name(), raw.parse().unwrap());
+        assert_eq!(get_typed::<T>(&headers), Some(header));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        roundtrip(
+            ContentType(mime::TEXT_HTML_UTF_8),
+            "text/html; charset=utf-8",
+        );
+        roundtrip(ContentLength(42), "42");
+        roundtrip(Allow(vec![Method::GET, Method::HEAD]), "GET, HEAD");
+        roundtrip(Authorization::Bearer("t0k3n".to_owned()), "Bearer t0k3n");
+        roundtrip(
+            Authorization::Basic {
+                username: "Aladdin".to_owned(),
+                password: Some("open sesame".to_owned()),
+            },
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==",
+        );
+        roundtrip(
+            CacheControl(vec![
+                CacheDirective::Private,
+                CacheDirective::MaxAge(60),
+                CacheDirective::Extension("community".to_owned(), Some("UCI".to_owned())),
+            ]),
+            "private, max-age=60, community=UCI",
+        );
+    }
+
+    #[test]
+    fn test_cache_control_quoted_args() {
+        roundtrip(
+            CacheControl(vec![
+                CacheDirective::Extension("private".to_owned(), Some("x, y".to_owned())),
+                CacheDirective::Extension("ext".to_owned(), Some("say \"hi\"".to_owned())),
+                CacheDirective::Extension("empty".to_owned(), Some("".to_owned())),
+                CacheDirective::MaxAge(60),
+            ]),
+            "private=\"x, y\", ext=\"say \\\"hi\\\"\", empty=\"\", max-age=60",
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CACHE_CONTROL, "max-age=\"60\", no-cache".parse().unwrap());
+        assert_eq!(
+            get_typed::<CacheControl>(&headers),
+            Some(CacheControl(vec![
+                CacheDirective::MaxAge(60),
+                CacheDirective::NoCache,
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_invalid_strings() {
+        let mut headers = HeaderMap::new();
+        assert!(set_typed(&mut headers, Location("/a\nb".to_owned())).is_err());
+        assert!(set_typed(&mut headers, UserAgent("agent\r\n".to_owned())).is_err());
+        assert!(set_typed(&mut headers, Authorization::Bearer("a\0b".to_owned())).is_err());
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_list_over_several_values() {
+        let mut headers = HeaderMap::new();
+        headers.append(CACHE_CONTROL, "no-cache".parse().unwrap());
+        headers.append(CACHE_CONTROL, "no-store, max-age=0".parse().unwrap());
+
+        assert_eq!(
+            get_typed::<CacheControl>(&headers),
+            Some(CacheControl(vec![
+                CacheDirective::NoCache,
+                CacheDirective::NoStore,
+                CacheDirective::MaxAge(0),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_typed::<ContentLength>(&headers), None);
+
+        headers.insert(CONTENT_LENGTH, "many".parse().unwrap());
+        headers.insert(CACHE_CONTROL, "max-age=soon".parse().unwrap());
+        headers.insert(AUTHORIZATION, "Digest username=\"x\"".parse().unwrap());
+        assert_eq!(get_typed::<ContentLength>(&headers), None);
+        assert_eq!(get_typed::<CacheControl>(&headers), None);
+        assert_eq!(get_typed::<Authorization>(&headers), None);
+    }
+}
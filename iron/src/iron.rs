@@ -1,22 +1,28 @@
 //! Exposes the `Iron` type, the main entrance point of the
 //! `Iron` library.
 
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::any::Any;
+use std::fmt;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
 use std::time::Duration;
 
 use futures::{future, Future};
 use futures_cpupool::CpuPool;
 
+use http;
 use hyper;
 use hyper::service::{NewService, Service};
 use hyper::Server;
 use hyper::{Body, Error};
 
+use headers::HeaderMap;
 use request::HttpRequest;
 use response::HttpResponse;
+use websocket::PendingUpgrade;
 
-use {Handler, Request, StatusCode};
+use {AsyncPool, Handler, IronBuilder, Method, Request, Response, StatusCode, Url};
 
 /// The primary entrance point to `Iron`, a `struct` to instantiate a new server.
 ///
@@ -36,6 +42,11 @@ pub struct Iron<H> {
     /// handled asyncronusly in a single thread.
     pub pool: CpuPool,
 
+    /// Threads to drive asynchronous handlers on, instead of `pool`.
+    ///
+    /// Defaults to `num_cpus`. See `Handler::as_async`.
+    pub async_pool: AsyncPool,
+
     /// Protocol of the incoming requests
     ///
     /// This is automatically set by the `http` and `https` functions, but
@@ -48,6 +59,81 @@ pub struct Iron<H> {
     /// provide one.  When unset, any request without a host specified
     /// will fail.
     pub local_address: Option<SocketAddr>,
+
+    /// Called with every panic caught while handling a request.
+    ///
+    /// A panicking `Handler` is logged and answered with `panic_response`, so
+    /// the client doesn't just see its connection reset.
+    pub panic_hook: Option<PanicHook>,
+
+    /// Creates the response sent when the `Handler` panics.
+    ///
+    /// Defaults to an empty `500 Internal Server Error` response.
+    pub panic_response: Option<PanicResponse>,
+
+    /// Answers requests which can't be turned into a `Request`, such as
+    /// requests without a host.
+    ///
+    /// It receives the error and the parts of the raw request. Defaults to
+    /// an empty `400 Bad Request` response.
+    pub bad_request_handler: Option<BadRequestHandler>,
+
+    /// The largest buffer used to read requests, limiting the size of the
+    /// request head.
+    ///
+    /// Defaults to hyper's limit of about 400kb. Must be at least 8192.
+    pub max_buf_size: Option<usize>,
+
+    /// Headers added to every response which doesn't set them itself, such
+    /// as `Server`.
+    pub default_headers: HeaderMap,
+}
+
+/// A function answering requests which can't be turned into a `Request`.
+pub type BadRequestHandler = Arc<dyn Fn(&str, &http::request::Parts) -> Response + Send + Sync>;
+
+/// A callback for panics caught while handling a request.
+pub type PanicHook = Arc<dyn Fn(&HandlerPanic) + Send + Sync>;
+
+/// A function creating the response sent when the `Handler` panics.
+pub type PanicResponse = Arc<dyn Fn(&HandlerPanic) -> Response + Send + Sync>;
+
+/// A panic caught while handling a request.
+#[derive(Debug, Clone)]
+pub struct HandlerPanic {
+    /// The method of the request.
+    pub method: Method,
+    /// The URL of the request.
+    pub url: Url,
+    /// The message the handler panicked with.
+    pub message: String,
+}
+
+impl HandlerPanic {
+    fn new(method: Method, url: Url, payload: Box<dyn Any + Send>) -> HandlerPanic {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => (*message).to_owned(),
+                Err(_) => "Box<Any>".to_owned(),
+            },
+        };
+        HandlerPanic {
+            method,
+            url,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for HandlerPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Handler panicked on {} {}: {}",
+            self.method, self.url, self.message
+        )
+    }
 }
 
 /// A settings struct containing a set of timeouts which can be applied to a server.
@@ -111,9 +197,22 @@ impl<H: Handler> Iron<H> {
             local_address: None,
             timeouts: Timeouts::default(),
             pool: CpuPool::new_num_cpus(),
+            async_pool: AsyncPool::new_num_cpus(),
+            panic_hook: None,
+            panic_response: None,
+            bad_request_handler: None,
+            max_buf_size: None,
+            default_headers: HeaderMap::new(),
         }
     }
 
+    /// Create a builder for `Iron`, validating its configuration.
+    ///
+    /// See `IronBuilder` for details.
+    pub fn builder(handler: H) -> IronBuilder<H> {
+        IronBuilder::new(handler)
+    }
+
     /// Kick off the server process using the HTTP protocol.
     ///
     /// Call this once to begin listening for requests on the server.
@@ -122,14 +221,33 @@ impl<H: Handler> Iron<H> {
         A: ToSocketAddrs,
     {
         let addr: SocketAddr = addr.to_socket_addrs().unwrap().next().unwrap();
+        let listener =
+            TcpListener::bind(addr).unwrap_or_else(|e| panic!("error binding to {}: {}", addr, e));
         self.local_address = Some(addr);
 
-        let server = Server::bind(&addr)
-            .tcp_keepalive(self.timeouts.keep_alive)
-            .serve(self)
-            .map_err(|e| eprintln!("server error: {}", e));
+        self.listen(listener, future::empty());
+    }
 
-        hyper::rt::run(server);
+    // Serve requests from `listener` until `shutdown` completes, blocking the
+    // current thread.
+    pub(crate) fn listen<F>(self, listener: TcpListener, shutdown: F)
+    where
+        F: Future<Item = (), Error = ()> + Send + 'static,
+    {
+        // The listener is registered with the reactor of the runtime, so the
+        // server is only created once it runs.
+        hyper::rt::run(future::lazy(move || {
+            let mut server = Server::from_tcp(listener)
+                .unwrap_or_else(|e| panic!("error listening: {}", e))
+                .tcp_keepalive(self.timeouts.keep_alive);
+            if let Some(max_buf_size) = self.max_buf_size {
+                server = server.http1_max_buf_size(max_buf_size);
+            }
+            server
+                .serve(self)
+                .with_graceful_shutdown(shutdown)
+                .map_err(|e| eprintln!("server error: {}", e))
+        }));
     }
 }
 
@@ -147,6 +265,13 @@ impl<H: Handler> NewService for Iron<H> {
             addr: self.local_address,
             protocol: self.protocol.clone(),
             pool: self.pool.clone(),
+            async_pool: self.async_pool.clone(),
+            panics: Panics {
+                hook: self.panic_hook.clone(),
+                response: self.panic_response.clone(),
+            },
+            bad_request: self.bad_request_handler.clone(),
+            default_headers: Arc::new(self.default_headers.clone()),
         })
     }
 }
@@ -157,6 +282,32 @@ pub struct IronHandler<H> {
     addr: Option<SocketAddr>,
     protocol: Protocol,
     pool: CpuPool,
+    async_pool: AsyncPool,
+    panics: Panics,
+    bad_request: Option<BadRequestHandler>,
+    default_headers: Arc<HeaderMap>,
+}
+
+// How panics of the handler are reported and answered.
+#[derive(Clone)]
+struct Panics {
+    hook: Option<PanicHook>,
+    response: Option<PanicResponse>,
+}
+
+impl Panics {
+    fn recover(&self, method: Method, url: Url, payload: Box<dyn Any + Send>) -> Response {
+        let panic = HandlerPanic::new(method, url, payload);
+        error!("{}", panic);
+
+        if let Some(ref hook) = self.hook {
+            hook(&panic);
+        }
+        match self.response {
+            Some(ref response) => response(&panic),
+            None => Response::with(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
 }
 
 impl<H: Handler> Service for IronHandler<H> {
@@ -166,34 +317,292 @@ impl<H: Handler> Service for IronHandler<H> {
     type Future = Box<dyn Future<Item = HttpResponse<Self::ResBody>, Error = Self::Error> + Send>;
 
     fn call(&mut self, req: HttpRequest<Self::ReqBody>) -> Self::Future {
+        let res = self.dispatch(req);
+        if self.default_headers.is_empty() {
+            return res;
+        }
+
+        let defaults = self.default_headers.clone();
+        Box::new(res.map(move |mut http_res| {
+            for (name, value) in defaults.iter() {
+                if !http_res.headers().contains_key(name) {
+                    http_res.headers_mut().insert(name, value.clone());
+                }
+            }
+            http_res
+        }))
+    }
+}
+
+impl<H: Handler> IronHandler<H> {
+    fn dispatch(&self, req: HttpRequest<Body>) -> <Self as Service>::Future {
+        if self.handler.as_async().is_some() {
+            return self.call_async(req);
+        }
+
         let addr = self.addr;
         let proto = self.protocol.clone();
         let handler = self.handler.clone();
+        let panics = self.panics.clone();
+        let bad_request_handler = self.bad_request.clone();
 
-        Box::new(self.pool.spawn_fn(move || {
-            let mut http_res = HttpResponse::<Body>::new(Body::empty());
-            *http_res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-
-            match Request::from_http(req, addr, &proto) {
-                Ok(mut req) => {
-                    // Dispatch the request, write the response back to http_res
-                    handler
-                        .handle(&mut req)
-                        .unwrap_or_else(|e| {
-                            error!("Error handling:\n{:?}\nError was: {:?}", req, e.error);
-                            e.response
-                        }).write_back(&mut http_res, req.method)
-                }
-                Err(e) => {
-                    error!("Error creating request:\n    {}", e);
-                    bad_request(&mut http_res)
-                }
-            };
-            future::ok(http_res)
-        }))
+        Box::new(
+            self.pool
+                .spawn_fn(move || {
+                    let mut http_res = HttpResponse::<Body>::new(Body::empty());
+                    *http_res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    let mut upgrade = None;
+
+                    let (parts, body) = req.into_parts();
+                    match Request::from_parts(parts, body, addr, &proto) {
+                        Ok(mut req) => {
+                            // Dispatch the request, write the response back to http_res
+                            let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                                match handler.handle(&mut req) {
+                                    Ok(res) => res,
+                                    Err(e) => {
+                                        error!(
+                                            "Error handling:\n{:?}\nError was: {}",
+                                            req,
+                                            e.chain()
+                                        );
+                                        e.response
+                                    }
+                                }
+                            }));
+                            let mut res = match handled {
+                                Ok(res) => res,
+                                Err(payload) => {
+                                    panics.recover(req.method.clone(), req.url.clone(), payload)
+                                }
+                            };
+                            upgrade = PendingUpgrade::take(&mut res);
+                            res.write_back(&mut http_res, req.method)
+                        }
+                        Err((e, parts)) => {
+                            bad_request(&bad_request_handler, &e, *parts, &mut http_res)
+                        }
+                    };
+                    future::ok((http_res, upgrade))
+                })
+                .map(|(http_res, upgrade)| upgraded(http_res, upgrade)),
+        )
+    }
+
+    // Asynchronous handlers are driven on a thread of the async pool, which
+    // the future of the handler, owning the `Request`, never leaves.
+    fn call_async(&self, req: HttpRequest<Body>) -> <Self as Service>::Future {
+        let addr = self.addr;
+        let proto = self.protocol.clone();
+        let handler = self.handler.clone();
+        let panics = self.panics.clone();
+        let bad_request_handler = self.bad_request.clone();
+
+        Box::new(
+            self.async_pool
+                .spawn_fn(move || -> Box<dyn Future<Item = _, Error = ()>> {
+                    let mut http_res = HttpResponse::<Body>::new(Body::empty());
+                    *http_res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+
+                    let (parts, body) = req.into_parts();
+                    let req = match Request::from_parts(parts, body, addr, &proto) {
+                        Ok(req) => req,
+                        Err((e, parts)) => {
+                            bad_request(&bad_request_handler, &e, *parts, &mut http_res);
+                            return Box::new(future::ok((http_res, None)));
+                        }
+                    };
+
+                    let method = req.method.clone();
+                    let url = req.url.clone();
+
+                    // Panics are caught both when creating the future and when
+                    // polling it.
+                    let future = match panic::catch_unwind(AssertUnwindSafe(|| {
+                        // unwrap is safe because the caller checked it's Some
+                        handler.as_async().unwrap().handle_owned(req)
+                    })) {
+                        Ok(future) => AssertUnwindSafe(future).catch_unwind(),
+                        Err(payload) => {
+                            let res = panics.recover(method.clone(), url, payload);
+                            res.write_back(&mut http_res, method);
+                            return Box::new(future::ok((http_res, None)));
+                        }
+                    };
+
+                    Box::new(future.then(move |result| {
+                        let mut res = match result {
+                            Ok(Ok((_, res))) => res,
+                            Ok(Err((_, e))) => {
+                                error!(
+                                    "Error handling {} {}\nError was: {}",
+                                    method,
+                                    url,
+                                    e.chain()
+                                );
+                                e.response
+                            }
+                            Err(payload) => panics.recover(method.clone(), url, payload),
+                        };
+                        let upgrade = PendingUpgrade::take(&mut res);
+                        res.write_back(&mut http_res, method);
+                        Ok((http_res, upgrade))
+                    }))
+                })
+                .then(|result| {
+                    Ok(match result {
+                        Ok(Ok((http_res, upgrade))) => upgraded(http_res, upgrade),
+                        // The future can't fail, and panics are caught, so the
+                        // thread of the pool must have failed to start.
+                        _ => {
+                            let mut http_res = HttpResponse::new(Body::empty());
+                            *http_res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                            http_res
+                        }
+                    })
+                }),
+        )
+    }
+}
+
+// Send the response of a request, upgrading the connection if the response
+// asks for it.
+fn upgraded(http_res: HttpResponse<Body>, upgrade: Option<PendingUpgrade>) -> HttpResponse<Body> {
+    // The connection is only upgraded once the response has been sent, which
+    // happens on the event loop we are running on.
+    if let Some(upgrade) = upgrade {
+        hyper::rt::spawn(upgrade.run());
+    }
+    http_res
+}
+
+// Answer a request which couldn't be turned into a `Request`.
+fn bad_request(
+    handler: &Option<BadRequestHandler>,
+    err: &str,
+    parts: http::request::Parts,
+    http_res: &mut HttpResponse<Body>,
+) {
+    error!("Error creating request:\n    {}", err);
+    match *handler {
+        Some(ref handler) => handler(err, &parts).write_back(http_res, parts.method),
+        None => *http_res.status_mut() = StatusCode::BAD_REQUEST,
     }
 }
 
-fn bad_request(http_res: &mut HttpResponse<Body>) {
-    *http_res.status_mut() = StatusCode::BAD_REQUEST;
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use futures::Stream;
+
+    use headers::{self, HeaderValue};
+    use middleware::Async;
+    use modifiers::Header;
+    use {Chain, IronFuture};
+
+    fn call<H: Handler>(iron: &Iron<H>) -> HttpResponse<Body> {
+        let mut service = iron.new_service().wait().unwrap();
+        let mut req = HttpRequest::new(Body::empty());
+        *req.uri_mut() = "http://localhost/boom".parse().unwrap();
+        service.call(req).wait().unwrap()
+    }
+
+    #[test]
+    fn test_handler_panic() {
+        let panics = Arc::new(Mutex::new(vec![]));
+        let seen = panics.clone();
+
+        let mut iron = Iron::new(|_: &mut Request| -> ::IronResult<Response> { panic!("boom") });
+        iron.panic_hook = Some(Arc::new(move |panic: &HandlerPanic| {
+            seen.lock().unwrap().push(panic.to_string());
+        }));
+
+        let res = call(&iron);
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            *panics.lock().unwrap(),
+            ["Handler panicked on GET http://localhost/boom: boom"]
+        );
+    }
+
+    #[test]
+    fn test_bad_request_handler() {
+        let mut iron = Iron::new(|_: &mut Request| Ok(Response::with(StatusCode::OK)));
+        let mut service = iron.new_service().wait().unwrap();
+        let res = service
+            .call(HttpRequest::new(Body::empty()))
+            .wait()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        iron.bad_request_handler = Some(Arc::new(|err: &str, parts: &http::request::Parts| {
+            let body = format!("{} for {} {}", err, parts.method, parts.uri);
+            Response::with((StatusCode::BAD_REQUEST, body))
+        }));
+        let mut service = iron.new_service().wait().unwrap();
+        let res = service
+            .call(HttpRequest::new(Body::empty()))
+            .wait()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body = res.into_body().concat2().wait().unwrap();
+        assert_eq!(&*body, &b"No host specified in request for GET /"[..]);
+    }
+
+    #[test]
+    fn test_async_handler_panic() {
+        let mut iron = Iron::new(Async(|_: &mut Request| -> IronFuture<Response> {
+            Box::new(future::poll_fn(
+                || -> ::futures::Poll<Response, ::IronError> { panic!("{} went wrong", "polling") },
+            ))
+        }));
+        iron.panic_response = Some(Arc::new(|panic: &HandlerPanic| {
+            assert_eq!(panic.message, "polling went wrong");
+            Response::with(StatusCode::SERVICE_UNAVAILABLE)
+        }));
+
+        let res = call(&iron);
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_async_chain_afters() {
+        let mut chain = Chain::new(Async(|_: &mut Request| -> IronFuture<Response> {
+            Box::new(future::poll_fn(|| {
+                Ok(::futures::Async::Ready(Response::with(StatusCode::OK)))
+            }))
+        }));
+        chain.link_after(|_: &mut Request, mut res: Response| {
+            res.headers
+                .insert(headers::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+            Ok(res)
+        });
+        let iron = Iron::new(chain);
+        assert!(iron.handler.as_async().is_some());
+
+        let res = call(&iron);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["cache-control"], "no-store");
+    }
+
+    #[test]
+    fn test_default_headers() {
+        let iron = Iron::builder(|_: &mut Request| {
+            Ok(Response::with((
+                StatusCode::OK,
+                Header(headers::CACHE_CONTROL, HeaderValue::from_static("no-store")),
+            )))
+        })
+        .server_header("iron")
+        .default_header("cache-control", "max-age=60")
+        .build()
+        .unwrap();
+
+        let res = call(&iron);
+        assert_eq!(res.headers()["server"], "iron");
+        assert_eq!(res.headers()["cache-control"], "no-store");
+    }
 }
@@ -68,13 +68,31 @@
 extern crate log;
 
 // Third party packages
+extern crate base64;
 extern crate futures;
 extern crate futures_cpupool;
 extern crate http;
+#[cfg(feature = "testing")]
+extern crate httparse;
 extern crate hyper;
+#[macro_use]
+extern crate lazy_static;
 pub extern crate mime;
 extern crate mime_guess;
+extern crate num_cpus;
 extern crate plugin;
+#[cfg(any(feature = "config", feature = "testing"))]
+extern crate serde;
+#[cfg(feature = "config")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "testing")]
+extern crate serde_json;
+extern crate sha1;
+extern crate tokio;
+extern crate tokio_io;
+#[cfg(feature = "config")]
+extern crate toml;
 extern crate typemap as tmap;
 extern crate url as url_ext;
 
@@ -83,16 +101,20 @@ pub use request::{Request, Url};
 pub use response::Response;
 
 // Middleware system
-pub use middleware::{AfterMiddleware, AroundMiddleware, BeforeMiddleware, Chain, Handler};
+pub use middleware::{
+    AfterMiddleware, AroundMiddleware, AsyncHandler, BeforeMiddleware, Chain, Handler,
+};
 
 // Server
+pub use async_pool::AsyncPool;
+pub use builder::*;
 pub use iron::*;
 
 // Extensions
 pub use typemap::TypeMap;
 
 // Headers
-pub use hyper::header as headers;
+pub mod headers;
 
 // Expose `Pluggable` as `Plugin` so users can do `use iron::Plugin`.
 pub use plugin::Pluggable as Plugin;
@@ -110,6 +132,17 @@ pub mod error;
 /// The Result alias used throughout Iron and in clients of Iron.
 pub type IronResult<T> = Result<T, IronError>;
 
+/// The Future alias used by asynchronous handlers.
+///
+/// It doesn't need to be `Send`, since `Iron` drives it on the thread of its
+/// `AsyncPool` which created it.
+pub type IronFuture<T> = Box<dyn futures::Future<Item = T, Error = IronError>>;
+
+/// The Future alias used by asynchronous handlers which own the `Request`,
+/// giving it back along with the result.
+pub type RequestFuture =
+    Box<dyn futures::Future<Item = (Request, Response), Error = (Request, IronError)>>;
+
 /// A module meant to be glob imported when using Iron.
 ///
 /// For instance:
@@ -161,7 +194,25 @@ pub mod request;
 // Request and Response Modifiers
 pub mod modifiers;
 
+// Server-Sent Events
+pub mod sse;
+
+// WebSocket upgrades
+pub mod websocket;
+
+// Utilities for testing handlers
+#[cfg(feature = "testing")]
+pub mod testing;
+
 // Helper macros for error handling
 mod macros;
 
 mod iron;
+
+mod async_pool;
+
+mod builder;
+
+// Server configuration files
+#[cfg(feature = "config")]
+pub mod config;
@@ -8,7 +8,9 @@
 /// request handlers.
 ///
 /// The second (optional) parameter is any [modifier](modifiers/index.html).
-/// The default modifier is `StatusCode::INTERNAL_SERVER_ERROR`.
+/// The default modifier is `error::DefaultStatus`, a `500 Internal Server Error`
+/// which an `error::ErrorResponses` registry can replace based on the type of
+/// the error.
 ///
 ///
 /// ```ignore
@@ -20,7 +22,7 @@
 #[macro_export]
 macro_rules! itry {
     ($result:expr) => {
-        itry!($result, $crate::StatusCode::INTERNAL_SERVER_ERROR)
+        itry!($result, $crate::error::DefaultStatus)
     };
 
     ($result:expr, $modifier:expr) => {
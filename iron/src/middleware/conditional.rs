@@ -0,0 +1,389 @@
+//! Middleware which only apply to some requests.
+
+use std::sync::Arc;
+
+use super::{AfterMiddleware, AroundMiddleware, AsyncHandler, BeforeMiddleware, Handler};
+use {IronError, IronFuture, IronResult, Method, Request, RequestFuture, Response};
+
+/// A condition on a `Request`, deciding whether a conditional middleware
+/// applies to it.
+///
+/// Implemented for closures taking a `&Request` and returning a `bool`.
+pub trait Predicate: Send + Sync + 'static {
+    /// Returns `true` if the middleware should apply to `req`.
+    fn matches(&self, req: &Request) -> bool;
+}
+
+impl<F> Predicate for F
+where
+    F: Send + Sync + 'static + Fn(&Request) -> bool,
+{
+    fn matches(&self, req: &Request) -> bool {
+        (*self)(req)
+    }
+}
+
+/// Apply `middleware` only to requests matching `predicate`.
+///
+/// The result is a middleware of the same kind as `middleware`, so it can be
+/// linked to a `Chain` in its place:
+///
+/// ```rust
+/// use iron::prelude::*;
+/// use iron::middleware::{method, path_prefix, unless, when};
+/// use iron::{Method, StatusCode};
+///
+/// fn authenticate(_: &mut Request) -> IronResult<()> {
+///     Ok(())
+/// }
+///
+/// let mut chain = Chain::new(|_: &mut Request| Ok(Response::with(StatusCode::OK)));
+/// chain.link_before(when(path_prefix("/admin"), authenticate));
+/// chain.link_before(unless(method(Method::GET), authenticate));
+/// ```
+///
+/// Requests which don't match pass through unchanged, and errors pass
+/// through the `catch` methods of the skipped middleware.
+pub fn when<P, M>(predicate: P, middleware: M) -> When<P, M>
+where
+    P: Predicate,
+{
+    When {
+        predicate,
+        middleware,
+    }
+}
+
+/// Apply `middleware` only to requests not matching `predicate`.
+pub fn unless<P, M>(predicate: P, middleware: M) -> When<Not<P>, M>
+where
+    P: Predicate,
+{
+    when(Not(predicate), middleware)
+}
+
+/// A middleware which only applies to requests matching a `Predicate`.
+///
+/// Created by `when` and `unless`.
+pub struct When<P, M> {
+    predicate: P,
+    middleware: M,
+}
+
+impl<P, M> BeforeMiddleware for When<P, M>
+where
+    P: Predicate,
+    M: BeforeMiddleware,
+{
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        if self.predicate.matches(req) {
+            self.middleware.before(req)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn respond(&self, req: &mut Request) -> IronResult<Option<Response>> {
+        if self.predicate.matches(req) {
+            self.middleware.respond(req)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn catch(&self, req: &mut Request, err: IronError) -> IronResult<()> {
+        if self.predicate.matches(req) {
+            self.middleware.catch(req, err)
+        } else {
+            Err(err)
+        }
+    }
+}
+
+impl<P, M> AfterMiddleware for When<P, M>
+where
+    P: Predicate,
+    M: AfterMiddleware,
+{
+    fn after(&self, req: &mut Request, res: Response) -> IronResult<Response> {
+        if self.predicate.matches(req) {
+            self.middleware.after(req, res)
+        } else {
+            Ok(res)
+        }
+    }
+
+    fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response> {
+        if self.predicate.matches(req) {
+            self.middleware.catch(req, err)
+        } else {
+            Err(err)
+        }
+    }
+}
+
+impl<P, M> AroundMiddleware for When<P, M>
+where
+    P: Predicate,
+    M: AroundMiddleware,
+{
+    fn around(self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+        let handler = Arc::new(handler);
+        let wrapped = self.middleware.around(Box::new(Shared(handler.clone())));
+        Box::new(WhenHandler {
+            predicate: self.predicate,
+            wrapped,
+            handler,
+        })
+    }
+}
+
+// The handler of a conditional around middleware, dispatching to either the
+// wrapped or the original handler.
+struct WhenHandler<P> {
+    predicate: P,
+    wrapped: Box<dyn Handler>,
+    handler: Arc<Box<dyn Handler>>,
+}
+
+impl<P: Predicate> Handler for WhenHandler<P> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        if self.predicate.matches(req) {
+            self.wrapped.handle(req)
+        } else {
+            self.handler.handle(req)
+        }
+    }
+
+    // Asynchronous only if both branches are, since the branch is picked
+    // once the request is known.
+    fn as_async(&self) -> Option<&dyn AsyncHandler> {
+        match (self.wrapped.as_async(), self.handler.as_async()) {
+            (Some(_), Some(_)) => Some(self),
+            _ => None,
+        }
+    }
+}
+
+impl<P: Predicate> AsyncHandler for WhenHandler<P> {
+    fn handle_async(&self, req: &mut Request) -> IronFuture<Response> {
+        self.branch(req).handle_async(req)
+    }
+
+    fn handle_owned(&self, req: Request) -> RequestFuture {
+        self.branch(&req).handle_owned(req)
+    }
+}
+
+impl<P: Predicate> WhenHandler<P> {
+    // The asynchronous handler of the branch `req` takes.
+    fn branch(&self, req: &Request) -> &dyn AsyncHandler {
+        let handler: &dyn Handler = if self.predicate.matches(req) {
+            &*self.wrapped
+        } else {
+            &**self.handler
+        };
+        // unwrap is safe because `as_async` checked both branches
+        handler.as_async().unwrap()
+    }
+}
+
+// A handler shared by both branches of a `WhenHandler`.
+struct Shared(Arc<Box<dyn Handler>>);
+
+impl Handler for Shared {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        self.0.handle(req)
+    }
+
+    fn as_async(&self) -> Option<&dyn AsyncHandler> {
+        self.0.as_async()
+    }
+}
+
+/// A `Predicate` matching requests which don't match the inner one.
+pub struct Not<P>(pub P);
+
+impl<P: Predicate> Predicate for Not<P> {
+    fn matches(&self, req: &Request) -> bool {
+        !self.0.matches(req)
+    }
+}
+
+/// Match requests with the given method.
+pub fn method(method: Method) -> MethodIs {
+    MethodIs(vec![method])
+}
+
+/// Match requests with any of the given methods.
+pub fn methods<I>(methods: I) -> MethodIs
+where
+    I: IntoIterator<Item = Method>,
+{
+    MethodIs(methods.into_iter().collect())
+}
+
+/// A `Predicate` matching the method of requests.
+///
+/// Created by `method` and `methods`.
+pub struct MethodIs(Vec<Method>);
+
+impl Predicate for MethodIs {
+    fn matches(&self, req: &Request) -> bool {
+        self.0.contains(&req.method)
+    }
+}
+
+/// Match requests for `path` exactly, ignoring a trailing slash.
+pub fn path(path: &str) -> PathIs {
+    PathIs {
+        segments: segments(path),
+        prefix: false,
+    }
+}
+
+/// Match requests for `prefix` or any path below it.
+///
+/// Paths are compared by segment, so `/admin` matches `/admin/users` but
+/// not `/administrator`.
+pub fn path_prefix(prefix: &str) -> PathIs {
+    PathIs {
+        segments: segments(prefix),
+        prefix: true,
+    }
+}
+
+/// A `Predicate` matching the path of requests.
+///
+/// Created by `path` and `path_prefix`.
+pub struct PathIs {
+    segments: Vec<String>,
+    prefix: bool,
+}
+
+impl Predicate for PathIs {
+    fn matches(&self, req: &Request) -> bool {
+        let path = req.url.path();
+        let path = path.iter().filter(|s| !s.is_empty()).collect::<Vec<_>>();
+
+        if self.prefix && path.len() < self.segments.len()
+            || !self.prefix && path.len() != self.segments.len()
+        {
+            return false;
+        }
+        self.segments.iter().zip(path).all(|(s, p)| s == p)
+    }
+}
+
+fn segments(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::Relaxed;
+
+    use futures::{future, Async as Poll, Future};
+
+    use middleware::Async;
+    use {Chain, StatusCode, Url};
+
+    fn request(method: Method, path: &str) -> Request {
+        let mut req = Request::stub();
+        req.method = method;
+        req.url = Url::parse(&format!("http://localhost{}", path)).unwrap();
+        req
+    }
+
+    #[test]
+    fn test_path_predicates() {
+        let admin = path_prefix("/admin/");
+        assert!(admin.matches(&request(Method::GET, "/admin")));
+        assert!(admin.matches(&request(Method::GET, "/admin/users")));
+        assert!(!admin.matches(&request(Method::GET, "/administrator")));
+        assert!(!admin.matches(&request(Method::GET, "/")));
+
+        let users = path("/admin/users");
+        assert!(users.matches(&request(Method::GET, "/admin/users/")));
+        assert!(!users.matches(&request(Method::GET, "/admin")));
+        assert!(!users.matches(&request(Method::GET, "/admin/users/1")));
+    }
+
+    #[test]
+    fn test_method_predicates() {
+        let writes = methods(vec![Method::POST, Method::PUT]);
+        assert!(writes.matches(&request(Method::PUT, "/")));
+        assert!(!writes.matches(&request(Method::GET, "/")));
+        assert!(Not(method(Method::GET)).matches(&request(Method::HEAD, "/")));
+    }
+
+    #[test]
+    fn test_conditional_middleware() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let (before, after) = (counter.clone(), counter.clone());
+
+        let mut chain = Chain::new(|_: &mut Request| Ok(Response::with(StatusCode::OK)));
+        chain.link_before(when(path_prefix("/admin"), move |_: &mut Request| {
+            before.fetch_add(1, Relaxed);
+            Ok(())
+        }));
+        chain.link_after(unless(method(Method::GET), move |_: &mut Request, res| {
+            after.fetch_add(10, Relaxed);
+            Ok(res)
+        }));
+        chain.link_around(when(method(Method::DELETE), |_: Box<dyn Handler>| {
+            Box::new(|_: &mut Request| Ok(Response::with(StatusCode::FORBIDDEN)))
+                as Box<dyn Handler>
+        }));
+
+        let res = chain.handle(&mut request(Method::GET, "/admin")).unwrap();
+        assert_eq!(res.status, Some(StatusCode::OK));
+        assert_eq!(counter.load(Relaxed), 1);
+
+        let res = chain.handle(&mut request(Method::DELETE, "/")).unwrap();
+        assert_eq!(res.status, Some(StatusCode::FORBIDDEN));
+        assert_eq!(counter.load(Relaxed), 11);
+    }
+
+    #[test]
+    fn test_conditional_around_async() {
+        fn respond(status: StatusCode) -> Async<impl AsyncHandler> {
+            Async(move |_: &mut Request| -> IronFuture<Response> {
+                Box::new(future::poll_fn(move || {
+                    Ok(Poll::Ready(Response::with(status)))
+                }))
+            })
+        }
+
+        let mut chain = Chain::new(respond(StatusCode::OK));
+        chain.link_around(when(method(Method::DELETE), |_: Box<dyn Handler>| {
+            Box::new(respond(StatusCode::FORBIDDEN)) as Box<dyn Handler>
+        }));
+        let handler = chain.as_async().unwrap();
+
+        let (_, res) = handler
+            .handle_owned(request(Method::GET, "/"))
+            .wait()
+            .ok()
+            .unwrap();
+        assert_eq!(res.status, Some(StatusCode::OK));
+        let (_, res) = handler
+            .handle_owned(request(Method::DELETE, "/"))
+            .wait()
+            .ok()
+            .unwrap();
+        assert_eq!(res.status, Some(StatusCode::FORBIDDEN));
+
+        // A synchronous branch makes the whole handler synchronous.
+        chain.link_around(when(method(Method::PUT), |_: Box<dyn Handler>| {
+            Box::new(|_: &mut Request| Ok(Response::with(StatusCode::CONFLICT))) as Box<dyn Handler>
+        }));
+        assert!(chain.as_async().is_none());
+    }
+}
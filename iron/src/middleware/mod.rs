@@ -95,6 +95,15 @@
 //! `Request` and `Response`. After all `AfterMiddleware` have been fired, the
 //! response is written back to the client.
 //!
+//! A `BeforeMiddleware` can also finish the request early by returning a
+//! `Response` from `respond`, for example on a cache hit. The remaining
+//! `BeforeMiddleware` and the `Handler` are skipped, and the `Response` is
+//! passed to all `AfterMiddleware` as if the `Handler` had produced it:
+//!
+//! ```plain
+//! [b] -> [b] -> [a] -> [a] -> [a] -> [a]
+//! ```
+//!
 //! Iron's error handling system is pragmatic and focuses on tracking two pieces
 //! of information for error receivers (other middleware):
 //!
@@ -128,13 +137,129 @@
 //! `Response` should be run during both the normal and error flow by
 //! implementing the `catch` method to also do the necessary action.
 
+use std::any::type_name;
+use std::error::Error;
+use std::fmt;
 use std::sync::Arc;
-use {IronError, IronResult, Request, Response};
+use std::time::{Duration, Instant};
+
+use futures::future::{self, FutureResult};
+use futures::Future;
+
+use self::timing::{ServerTimingOwner, Stage, Timing, Timings};
+use {IronError, IronFuture, IronResult, Request, RequestFuture, Response, StatusCode};
+
+pub use self::conditional::{
+    method, methods, path, path_prefix, unless, when, MethodIs, Not, PathIs, Predicate, When,
+};
+
+mod conditional;
+pub mod timing;
 
 /// `Handler`s are responsible for handling requests by creating Responses from Requests.
 pub trait Handler: Send + Sync + 'static {
     /// Produce a `Response` from a Request, with the possibility of error.
     fn handle(&self, &mut Request) -> IronResult<Response>;
+
+    /// This handler as an `AsyncHandler`, if it can produce its `Response`
+    /// without blocking.
+    ///
+    /// `Iron` drives asynchronous handlers on its `AsyncPool` instead of
+    /// calling `handle` on its thread pool.
+    fn as_async(&self) -> Option<&dyn AsyncHandler> {
+        None
+    }
+}
+
+/// `AsyncHandler`s are responsible for handling requests by creating future
+/// Responses from Requests.
+///
+/// `handle_owned` is called on a thread of Iron's `AsyncPool`, and the
+/// returned future is driven there along with the futures of other requests,
+/// so neither may block. An `AsyncHandler` is served by wrapping it in
+/// `Async`:
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate iron;
+/// # use std::io;
+/// use futures::Future;
+/// use iron::prelude::*;
+/// use iron::middleware::Async;
+/// use iron::{IronFuture, StatusCode};
+///
+/// fn lookup(req: &mut Request) -> IronFuture<Response> {
+///     let name = req.url.path().join("/");
+///     let upstream = futures::future::ok::<_, io::Error>(name);
+///     Box::new(
+///         upstream
+///             .map(|name| Response::with((StatusCode::OK, name)))
+///             .map_err(|err| IronError::new(err, StatusCode::BAD_GATEWAY)),
+///     )
+/// }
+///
+/// # fn main() {
+/// let chain = Chain::new(Async(lookup));
+/// // Iron::new(chain).http("localhost:3000");
+/// # }
+/// ```
+///
+/// The future of `handle_async` can't borrow the `Request`; copy what you
+/// need out of it before returning the future, or implement `handle_owned`.
+pub trait AsyncHandler: Send + Sync + 'static {
+    /// Produce a future `Response` from a Request, with the possibility of error.
+    fn handle_async(&self, req: &mut Request) -> IronFuture<Response>;
+
+    /// Produce a future `Response` from a Request owned by the future, which
+    /// gives the `Request` back when it completes.
+    ///
+    /// This is what `Iron` calls.
+    ///
+    /// Defaults to `handle_async`.
+    fn handle_owned(&self, mut req: Request) -> RequestFuture {
+        let future = self.handle_async(&mut req);
+        Box::new(future.then(move |result| with_request(req, result)))
+    }
+}
+
+/// Serves an `AsyncHandler` as a `Handler`.
+///
+/// `Iron` recognizes the wrapped handler and drives it asynchronously, as
+/// long as every handler wrapping it forwards `Handler::as_async`, like
+/// `Chain` does.
+///
+/// Where a synchronous `Handler` is required instead, such as when it is
+/// routed to by a `Router` or wrapped by an `AroundMiddleware`, waiting for
+/// the future could block a thread of `Iron` indefinitely, so `handle` fails
+/// with `AsyncOnly`.
+pub struct Async<A>(pub A);
+
+impl<A: AsyncHandler> Handler for Async<A> {
+    /// Fail with `AsyncOnly` and a `500 Internal Server Error` response.
+    fn handle(&self, _: &mut Request) -> IronResult<Response> {
+        Err(async_only())
+    }
+
+    fn as_async(&self) -> Option<&dyn AsyncHandler> {
+        Some(&self.0)
+    }
+}
+
+/// The error of an asynchronous handler which can't be served without
+/// blocking, such as an `Async` handler called through `Handler::handle`.
+#[derive(Debug)]
+pub struct AsyncOnly;
+
+impl fmt::Display for AsyncOnly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Asynchronous handler can't be served without blocking")
+    }
+}
+
+impl Error for AsyncOnly {}
+
+fn async_only() -> IronError {
+    IronError::new(AsyncOnly, StatusCode::INTERNAL_SERVER_ERROR)
 }
 
 /// `BeforeMiddleware` are fired before a `Handler` is called inside of a Chain.
@@ -145,14 +270,26 @@ pub trait Handler: Send + Sync + 'static {
 ///
 /// `BeforeMiddleware` only have access to the Request, if you need to modify or read
 /// a Response, you will need `AfterMiddleware`. Middleware which wishes to send an
-/// early response that is not an error cannot be `BeforeMiddleware`, but should
-/// instead be `AroundMiddleware`.
+/// early response that is not an error, such as a cached response or a redirect
+/// to a login page, should implement `respond`.
 pub trait BeforeMiddleware: Send + Sync + 'static {
     /// Do whatever work this middleware should do with a `Request` object.
     fn before(&self, _: &mut Request) -> IronResult<()> {
         Ok(())
     }
 
+    /// Do whatever work this middleware should do with a `Request` object,
+    /// possibly finishing the request with a `Response`.
+    ///
+    /// Returning `Ok(Some(response))` skips the remaining `BeforeMiddleware`
+    /// and the `Handler`, and resumes the normal flow at the first
+    /// `AfterMiddleware` with `response`.
+    ///
+    /// Defaults to calling `before` and continuing the request.
+    fn respond(&self, req: &mut Request) -> IronResult<Option<Response>> {
+        self.before(req).map(|()| None)
+    }
+
     /// Respond to an error thrown by a previous `BeforeMiddleware`.
     ///
     /// Returning a `Ok` will cause the request to resume the normal flow at the
@@ -209,12 +346,45 @@ pub trait AroundMiddleware {
 /// This is a canonical implementation of Iron's middleware system,
 /// but Iron's infrastructure is flexible enough to allow alternate
 /// systems.
+///
+/// Linked middleware are named after their type, or given a name with the
+/// `_named` variants of the link methods. The names show up in `describe`
+/// and in the debug logs of the request flow.
+///
+/// A `Chain` can also time each stage of the flow; see `record_timings` and
+/// `server_timing`.
 pub struct Chain {
-    befores: Vec<Box<dyn BeforeMiddleware>>,
-    afters: Vec<Box<dyn AfterMiddleware>>,
+    befores: Vec<Link<Box<dyn BeforeMiddleware>>>,
+    // Shared with the futures of asynchronous handlers, which run the after
+    // middleware once they complete.
+    afters: Arc<Vec<Link<Arc<dyn AfterMiddleware>>>>,
 
     // Internal invariant: this is always Some
     handler: Option<Box<dyn Handler>>,
+    handler_name: String,
+
+    // Names of the linked around middleware, innermost first
+    arounds: Vec<String>,
+
+    timings: bool,
+    server_timing: bool,
+    server_timing_names: bool,
+}
+
+// A middleware linked to a `Chain`, along with its name.
+#[derive(Clone)]
+struct Link<M> {
+    name: String,
+    middleware: M,
+}
+
+impl<M> Link<M> {
+    fn new<N: Into<String>>(name: N, middleware: M) -> Link<M> {
+        Link {
+            name: name.into(),
+            middleware,
+        }
+    }
 }
 
 impl Chain {
@@ -222,8 +392,13 @@ impl Chain {
     pub fn new<H: Handler>(handler: H) -> Chain {
         Chain {
             befores: vec![],
-            afters: vec![],
+            afters: Arc::new(vec![]),
             handler: Some(Box::new(handler) as Box<dyn Handler>),
+            handler_name: type_name::<H>().to_owned(),
+            arounds: vec![],
+            timings: false,
+            server_timing: false,
+            server_timing_names: false,
         }
     }
 
@@ -237,9 +412,7 @@ impl Chain {
         B: BeforeMiddleware,
     {
         let (before, after) = link;
-        self.befores.push(Box::new(before) as Box<dyn BeforeMiddleware>);
-        self.afters.push(Box::new(after) as Box<dyn AfterMiddleware>);
-        self
+        self.link_before(before).link_after(after)
     }
 
     /// Link a `BeforeMiddleware` to the `Chain`, after all previously linked
@@ -248,7 +421,17 @@ impl Chain {
     where
         B: BeforeMiddleware,
     {
-        self.befores.push(Box::new(before) as Box<dyn BeforeMiddleware>);
+        self.link_before_named(type_name::<B>(), before)
+    }
+
+    /// Link a `BeforeMiddleware` to the `Chain` under the given name.
+    pub fn link_before_named<N, B>(&mut self, name: N, before: B) -> &mut Chain
+    where
+        N: Into<String>,
+        B: BeforeMiddleware,
+    {
+        let before = Box::new(before) as Box<dyn BeforeMiddleware>;
+        self.befores.push(Link::new(name, before));
         self
     }
 
@@ -258,7 +441,18 @@ impl Chain {
     where
         A: AfterMiddleware,
     {
-        self.afters.push(Box::new(after) as Box<dyn AfterMiddleware>);
+        self.link_after_named(type_name::<A>(), after)
+    }
+
+    /// Link a `AfterMiddleware` to the `Chain` under the given name.
+    pub fn link_after_named<N, A>(&mut self, name: N, after: A) -> &mut Chain
+    where
+        N: Into<String>,
+        A: AfterMiddleware,
+    {
+        let after = Arc::new(after) as Arc<dyn AfterMiddleware>;
+        // Copies the after middleware if requests still hold on to them.
+        Arc::make_mut(&mut self.afters).push(Link::new(name, after));
         self
     }
 
@@ -277,25 +471,315 @@ impl Chain {
     pub fn link_around<A>(&mut self, around: A) -> &mut Chain
     where
         A: AroundMiddleware,
+    {
+        self.link_around_named(type_name::<A>(), around)
+    }
+
+    /// Apply an `AroundMiddleware` to the `Handler` in this `Chain` under the
+    /// given name.
+    pub fn link_around_named<N, A>(&mut self, name: N, around: A) -> &mut Chain
+    where
+        N: Into<String>,
+        A: AroundMiddleware,
     {
         let mut handler = self.handler.take().unwrap();
         handler = around.around(handler);
         self.handler = Some(handler);
+        self.arounds.push(name.into());
         self
     }
+
+    /// Record how long each middleware and the handler take.
+    ///
+    /// Timings are logged at debug level and stored in the `Timings`
+    /// extension of the `Request`, so they can be read by later middleware.
+    pub fn record_timings(&mut self) -> &mut Chain {
+        self.timings = true;
+        self
+    }
+
+    /// Record timings, and send them to the client in a `Server-Timing`
+    /// header.
+    ///
+    /// The metrics are only labelled by their stage and position, like
+    /// `before-0`; see `server_timing_names` to send the names of the
+    /// middleware as well.
+    ///
+    /// Only the outermost `Chain` of nested chains writes the header, which
+    /// includes the timings of the inner chains.
+    pub fn server_timing(&mut self) -> &mut Chain {
+        self.timings = true;
+        self.server_timing = true;
+        self
+    }
+
+    /// Send timings in a `Server-Timing` header like `server_timing`, with
+    /// the names of the middleware and handlers as descriptions.
+    ///
+    /// Unless given with the `_named` link methods, the names are type names
+    /// revealing how the application is built, so only enable this for
+    /// trusted clients.
+    pub fn server_timing_names(&mut self) -> &mut Chain {
+        self.server_timing_names = true;
+        self.server_timing()
+    }
+
+    /// Describe the middleware linked to this `Chain`, in the order they run.
+    pub fn describe(&self) -> ChainDescription {
+        ChainDescription {
+            befores: self.befores.iter().map(|b| b.name.clone()).collect(),
+            arounds: self.arounds.iter().rev().cloned().collect(),
+            handler: self.handler_name.clone(),
+            afters: self.afters.iter().map(|a| a.name.clone()).collect(),
+        }
+    }
+}
+
+impl fmt::Debug for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Chain").field(&self.describe()).finish()
+    }
+}
+
+/// The structure of a `Chain`, as returned by `Chain::describe`.
+///
+/// Displays as one middleware per line, in the order they run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainDescription {
+    /// The names of the `BeforeMiddleware`.
+    pub befores: Vec<String>,
+    /// The names of the `AroundMiddleware`, outermost first.
+    pub arounds: Vec<String>,
+    /// The name of the `Handler` wrapped by the `AroundMiddleware`.
+    pub handler: String,
+    /// The names of the `AfterMiddleware`.
+    pub afters: Vec<String>,
+}
+
+impl fmt::Display for ChainDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for before in &self.befores {
+            writeln!(f, "before {}", before)?;
+        }
+        for around in &self.arounds {
+            writeln!(f, "around {}", around)?;
+        }
+        writeln!(f, "handler {}", self.handler)?;
+        for after in &self.afters {
+            writeln!(f, "after {}", after)?;
+        }
+        Ok(())
+    }
 }
 
 impl Handler for Chain {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let owner = self.own_server_timing(req);
+
         // Kick off at befores, which will continue into handler
         // then afters.
-        self.continue_from_before(req, 0)
+        let mut result = self.continue_from_before(req, 0);
+
+        if owner {
+            add_request_timings(req, &mut result, self.server_timing_names);
+        }
+        result
+    }
+
+    /// A `Chain` is asynchronous if its `Handler` is.
+    fn as_async(&self) -> Option<&dyn AsyncHandler> {
+        self.handler.as_ref().unwrap().as_async().map(|_| self as _)
+    }
+}
+
+impl AsyncHandler for Chain {
+    /// Run the `BeforeMiddleware`, then produce the future of the `Handler`.
+    ///
+    /// The future can't hold on to the `Request` the `AfterMiddleware` need,
+    /// so a chain with `AfterMiddleware` fails with `AsyncOnly` instead; use
+    /// `handle_owned`.
+    ///
+    /// See `handle_owned` about blocking middleware.
+    fn handle_async(&self, req: &mut Request) -> IronFuture<Response> {
+        if !self.afters.is_empty() {
+            return Box::new(future::err(async_only()));
+        }
+
+        let owner = self.own_server_timing(req);
+
+        let (future, handled) = match self.run_befores(req) {
+            Befores::Handler => {
+                debug!("handler {} running", self.handler_name);
+                // unwrap is safe because it's always Some
+                let handler = self.handler.as_ref().unwrap();
+                let future = match handler.as_async() {
+                    Some(handler) => handler.handle_async(req),
+                    None => Box::new(future::result(handler.handle(req))),
+                };
+                (future, true)
+            }
+            Befores::Responded(res) => (Box::new(future::ok(res)) as IronFuture<_>, false),
+            Befores::Failed(err) => (Box::new(future::err(err)) as IronFuture<_>, false),
+        };
+
+        if self.timings {
+            self.time_async(req, future, handled, owner)
+        } else {
+            future
+        }
+    }
+
+    /// Run the `BeforeMiddleware`, then the future of the `Handler`, then the
+    /// `AfterMiddleware` once it completes.
+    ///
+    /// The middleware run on the thread of the `AsyncPool` driving the
+    /// future, along with the futures of other requests, so they must not
+    /// block. Middleware taking longer than 10ms are logged as warnings.
+    fn handle_owned(&self, mut req: Request) -> RequestFuture {
+        let owner = self.own_server_timing(&mut req);
+
+        let future: RequestFuture = match self.run_befores(&mut req) {
+            Befores::Handler => {
+                debug!("handler {} running", self.handler_name);
+                let start = Instant::now();
+                // unwrap is safe because it's always Some
+                let handler = self.handler.as_ref().unwrap();
+                let future: RequestFuture = match handler.as_async() {
+                    Some(handler) => handler.handle_owned(req),
+                    None => {
+                        let result = handler.handle(&mut req);
+                        Box::new(with_request(req, result))
+                    }
+                };
+
+                let timings = self.timings;
+                let name = self.handler_name.clone();
+                Box::new(future.then(move |result| {
+                    let (mut req, result) = split_request(result);
+                    if timings {
+                        record_timing(&mut req, Stage::Handler, &name, start.elapsed());
+                    }
+                    if let Err(ref err) = result {
+                        debug!("handler {} errored: {}", name, err);
+                    }
+                    with_request(req, result)
+                }))
+            }
+            Befores::Responded(res) => Box::new(future::ok((req, res))),
+            Befores::Failed(err) => Box::new(future::err((req, err))),
+        };
+
+        let afters = self.afters.clone();
+        let clock = self.clock(true);
+        let names = self.server_timing_names;
+        Box::new(future.then(move |result| {
+            let (mut req, result) = split_request(result);
+            let mut result = match result {
+                Ok(res) => continue_from_after(&afters, clock, &mut req, 0, res),
+                Err(err) => fail_from_after(&afters, clock, &mut req, 0, err),
+            };
+            if owner {
+                add_request_timings(&req, &mut result, names);
+            }
+            with_request(req, result)
+        }))
     }
 }
 
 impl Chain {
     ///////////////// Implementation Helpers /////////////////
 
+    // Whether this chain writes the Server-Timing header of the request,
+    // which is up to the outermost chain.
+    fn own_server_timing(&self, req: &mut Request) -> bool {
+        if !self.server_timing || req.extensions.contains::<ServerTimingOwner>() {
+            return false;
+        }
+        req.extensions.insert::<ServerTimingOwner>(());
+        true
+    }
+
+    // How the stages of the synchronous flow, or of the asynchronous flow on
+    // a thread of the `AsyncPool`, are timed.
+    fn clock(&self, on_async_pool: bool) -> Clock {
+        Clock {
+            record: self.timings,
+            on_async_pool,
+        }
+    }
+
+    // Time the future of an asynchronous handler. It completes after the
+    // `Request` is gone, so its timing is only logged and sent in the
+    // Server-Timing header.
+    fn time_async(
+        &self,
+        req: &Request,
+        future: IronFuture<Response>,
+        handled: bool,
+        owner: bool,
+    ) -> IronFuture<Response> {
+        let mut timings = req.extensions.get::<Timings>().cloned().unwrap_or_default();
+        let name = self.handler_name.clone();
+        let names = self.server_timing_names;
+        let start = Instant::now();
+
+        Box::new(future.then(move |mut result| {
+            if handled {
+                let duration = start.elapsed();
+                debug!("{} {} took {:?}", Stage::Handler, name, duration);
+                timings.push(Timing {
+                    stage: Stage::Handler,
+                    name,
+                    duration,
+                });
+            }
+            if owner {
+                add_server_timing(response_of(&mut result), &timings, names);
+            }
+            future::result(result)
+        }))
+    }
+
+    // Run all before middleware, including their error flow, for an
+    // asynchronous handler.
+    fn run_befores(&self, req: &mut Request) -> Befores {
+        let clock = self.clock(true);
+        let mut result = Ok(());
+        for before in &self.befores {
+            result = match result {
+                Ok(()) => {
+                    debug!("before middleware {} running", before.name);
+                    let start = clock.start();
+                    let respond = before.middleware.respond(req);
+                    clock.stop(start, req, Stage::Before, &before.name);
+                    match respond {
+                        Ok(None) => Ok(()),
+                        Ok(Some(res)) => {
+                            debug!("before middleware {} responded", before.name);
+                            return Befores::Responded(res);
+                        }
+                        Err(err) => {
+                            debug!("before middleware {} errored: {}", before.name, err);
+                            Err(err)
+                        }
+                    }
+                }
+                Err(err) => {
+                    let start = clock.start();
+                    let caught = before.middleware.catch(req, err);
+                    clock.stop(start, req, Stage::Catch, &before.name);
+                    caught.map(|()| {
+                        debug!("before middleware {} recovered", before.name);
+                    })
+                }
+            };
+        }
+        match result {
+            Ok(()) => Befores::Handler,
+            Err(err) => Befores::Failed(err),
+        }
+    }
+
     // Enter the error flow from a before middleware, starting
     // at the passed index.
     //
@@ -312,10 +796,17 @@ impl Chain {
             return self.fail_from_handler(req, err);
         }
 
+        let clock = self.clock(false);
         for (i, before) in self.befores[index..].iter().enumerate() {
-            err = match before.catch(req, err) {
+            let start = clock.start();
+            let caught = before.middleware.catch(req, err);
+            clock.stop(start, req, Stage::Catch, &before.name);
+            err = match caught {
                 Err(err) => err,
-                Ok(()) => return self.continue_from_before(req, index + i + 1),
+                Ok(()) => {
+                    debug!("before middleware {} recovered", before.name);
+                    return self.continue_from_before(req, index + i + 1);
+                }
             };
         }
 
@@ -327,34 +818,7 @@ impl Chain {
     // first AfterMiddleware.
     fn fail_from_handler(&self, req: &mut Request, err: IronError) -> IronResult<Response> {
         // Yield to next phase, nothing to do here.
-        self.fail_from_after(req, 0, err)
-    }
-
-    // Enter the error flow from an errored after middleware, starting
-    // with the passed index.
-    //
-    // If the index is out of bounds for the after middleware Vec,
-    // this instead just returns the passed error.
-    fn fail_from_after(
-        &self,
-        req: &mut Request,
-        index: usize,
-        mut err: IronError,
-    ) -> IronResult<Response> {
-        // If this was the last after, we're done.
-        if index == self.afters.len() {
-            return Err(err);
-        }
-
-        for (i, after) in self.afters[index..].iter().enumerate() {
-            err = match after.catch(req, err) {
-                Err(err) => err,
-                Ok(res) => return self.continue_from_after(req, index + i + 1, res),
-            }
-        }
-
-        // Done
-        Err(err)
+        fail_from_after(&self.afters, self.clock(false), req, 0, err)
     }
 
     // Enter the normal flow in the before middleware, starting with the passed
@@ -365,10 +829,23 @@ impl Chain {
             return self.continue_from_handler(req);
         }
 
+        let clock = self.clock(false);
         for (i, before) in self.befores[index..].iter().enumerate() {
-            match before.before(req) {
-                Ok(()) => {}
-                Err(err) => return self.fail_from_before(req, index + i + 1, err),
+            debug!("before middleware {} running", before.name);
+            let start = clock.start();
+            let respond = before.middleware.respond(req);
+            clock.stop(start, req, Stage::Before, &before.name);
+            match respond {
+                Ok(None) => {}
+                // Skip the handler, straight to the after middleware.
+                Ok(Some(res)) => {
+                    debug!("before middleware {} responded", before.name);
+                    return continue_from_after(&self.afters, clock, req, 0, res);
+                }
+                Err(err) => {
+                    debug!("before middleware {} errored: {}", before.name, err);
+                    return self.fail_from_before(req, index + i + 1, err);
+                }
             }
         }
 
@@ -378,35 +855,196 @@ impl Chain {
 
     // Enter the normal flow at the handler.
     fn continue_from_handler(&self, req: &mut Request) -> IronResult<Response> {
+        debug!("handler {} running", self.handler_name);
+        let clock = self.clock(false);
+        let start = clock.start();
         // unwrap is safe because it's always Some
-        match self.handler.as_ref().unwrap().handle(req) {
-            Ok(res) => self.continue_from_after(req, 0, res),
-            Err(err) => self.fail_from_handler(req, err),
+        let handled = self.handler.as_ref().unwrap().handle(req);
+        clock.stop(start, req, Stage::Handler, &self.handler_name);
+        match handled {
+            Ok(res) => continue_from_after(&self.afters, clock, req, 0, res),
+            Err(err) => {
+                debug!("handler {} errored: {}", self.handler_name, err);
+                self.fail_from_handler(req, err)
+            }
         }
     }
+}
 
-    // Enter the normal flow in the after middleware, starting with the passed
-    // index.
-    fn continue_from_after(
-        &self,
-        req: &mut Request,
-        index: usize,
-        mut res: Response,
-    ) -> IronResult<Response> {
-        // If this was the last after middleware, we're done.
-        if index >= self.afters.len() {
-            return Ok(res);
+// The after middleware of a `Chain`.
+type Afters = [Link<Arc<dyn AfterMiddleware>>];
+
+// Enter the normal flow in the after middleware, starting with the passed
+// index.
+fn continue_from_after(
+    afters: &Afters,
+    clock: Clock,
+    req: &mut Request,
+    index: usize,
+    mut res: Response,
+) -> IronResult<Response> {
+    // If this was the last after middleware, we're done.
+    if index >= afters.len() {
+        return Ok(res);
+    }
+
+    for (i, after) in afters[index..].iter().enumerate() {
+        debug!("after middleware {} running", after.name);
+        let start = clock.start();
+        let result = after.middleware.after(req, res);
+        clock.stop(start, req, Stage::After, &after.name);
+        res = match result {
+            Ok(r) => r,
+            Err(err) => {
+                debug!("after middleware {} errored: {}", after.name, err);
+                return fail_from_after(afters, clock, req, index + i + 1, err);
+            }
         }
+    }
+
+    // We made it with no error!
+    Ok(res)
+}
 
-        for (i, after) in self.afters[index..].iter().enumerate() {
-            res = match after.after(req, res) {
-                Ok(r) => r,
-                Err(err) => return self.fail_from_after(req, index + i + 1, err),
+// Enter the error flow from an errored after middleware, starting
+// with the passed index.
+//
+// If the index is out of bounds for the after middleware Vec,
+// this instead just returns the passed error.
+fn fail_from_after(
+    afters: &Afters,
+    clock: Clock,
+    req: &mut Request,
+    index: usize,
+    mut err: IronError,
+) -> IronResult<Response> {
+    // If this was the last after, we're done.
+    if index == afters.len() {
+        return Err(err);
+    }
+
+    for (i, after) in afters[index..].iter().enumerate() {
+        let start = clock.start();
+        let caught = after.middleware.catch(req, err);
+        clock.stop(start, req, Stage::Catch, &after.name);
+        err = match caught {
+            Err(err) => err,
+            Ok(res) => {
+                debug!("after middleware {} recovered", after.name);
+                return continue_from_after(afters, clock, req, index + i + 1, res);
             }
         }
+    }
 
-        // We made it with no error!
-        Ok(res)
+    // Done
+    Err(err)
+}
+
+// How the before middleware of an asynchronous flow finished.
+enum Befores {
+    // The request continues at the handler.
+    Handler,
+    // A before middleware finished the request with a response.
+    Responded(Response),
+    // The error wasn't handled by any before middleware.
+    Failed(IronError),
+}
+
+// A middleware of an asynchronous flow taking longer than this is logged as
+// blocking the thread of the `AsyncPool` it runs on.
+const BLOCKING_STAGE: Duration = Duration::from_millis(10);
+
+// How the stages of a request flow are timed.
+#[derive(Clone, Copy)]
+struct Clock {
+    // Whether timings are recorded in the `Request`
+    record: bool,
+    // Whether the flow runs on a thread of the `AsyncPool`, which must not be
+    // blocked
+    on_async_pool: bool,
+}
+
+impl Clock {
+    // Start timing a stage of the request flow, if its duration is needed.
+    fn start(self) -> Option<Instant> {
+        if self.record || self.on_async_pool {
+            Some(Instant::now())
+        } else {
+            None
+        }
+    }
+
+    // Finish timing a stage started with `start`, recording how long it
+    // took if timings are enabled.
+    fn stop(self, start: Option<Instant>, req: &mut Request, stage: Stage, name: &str) {
+        let duration = match start {
+            Some(start) => start.elapsed(),
+            None => return,
+        };
+        if self.on_async_pool && duration > BLOCKING_STAGE {
+            warn!(
+                "{} {} blocked the AsyncPool for {:?}, middleware of asynchronous chains must not block",
+                stage, name, duration
+            );
+        }
+        if self.record {
+            record_timing(req, stage, name, duration);
+        }
+    }
+}
+
+fn record_timing(req: &mut Request, stage: Stage, name: &str, duration: Duration) {
+    debug!("{} {} took {:?}", stage, name, duration);
+    req.extensions
+        .entry::<Timings>()
+        .or_insert_with(Vec::new)
+        .push(Timing {
+            stage,
+            name: name.to_owned(),
+            duration,
+        });
+}
+
+// Send the timings recorded for the request in the Server-Timing header.
+fn add_request_timings(req: &Request, result: &mut IronResult<Response>, names: bool) {
+    if let Some(timings) = req.extensions.get::<Timings>() {
+        add_server_timing(response_of(result), timings, names);
+    }
+}
+
+// Give the request back along with the result of handling it, as the
+// future of `AsyncHandler::handle_owned` does.
+fn with_request(
+    req: Request,
+    result: IronResult<Response>,
+) -> FutureResult<(Request, Response), (Request, IronError)> {
+    match result {
+        Ok(res) => future::ok((req, res)),
+        Err(err) => future::err((req, err)),
+    }
+}
+
+// The inverse of `with_request`.
+fn split_request(
+    result: Result<(Request, Response), (Request, IronError)>,
+) -> (Request, IronResult<Response>) {
+    match result {
+        Ok((req, res)) => (req, Ok(res)),
+        Err((req, err)) => (req, Err(err)),
+    }
+}
+
+// The response of a result, whether it's an error or not.
+fn response_of(result: &mut IronResult<Response>) -> &mut Response {
+    match *result {
+        Ok(ref mut res) => res,
+        Err(ref mut err) => &mut err.response,
+    }
+}
+
+fn add_server_timing(res: &mut Response, timings: &[Timing], names: bool) {
+    if let Some(value) = timing::server_timing(timings, names) {
+        res.headers.append(timing::server_timing_name(), value);
     }
 }
 
@@ -423,6 +1061,29 @@ impl Handler for Box<dyn Handler> {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
         (**self).handle(req)
     }
+
+    fn as_async(&self) -> Option<&dyn AsyncHandler> {
+        (**self).as_async()
+    }
+}
+
+impl<F> AsyncHandler for F
+where
+    F: Send + Sync + 'static + Fn(&mut Request) -> IronFuture<Response>,
+{
+    fn handle_async(&self, req: &mut Request) -> IronFuture<Response> {
+        (*self)(req)
+    }
+}
+
+impl AsyncHandler for Box<dyn AsyncHandler> {
+    fn handle_async(&self, req: &mut Request) -> IronFuture<Response> {
+        (**self).handle_async(req)
+    }
+
+    fn handle_owned(&self, req: Request) -> RequestFuture {
+        (**self).handle_owned(req)
+    }
 }
 
 impl<F> BeforeMiddleware for F
@@ -439,6 +1100,10 @@ impl BeforeMiddleware for Box<dyn BeforeMiddleware> {
         (**self).before(req)
     }
 
+    fn respond(&self, req: &mut Request) -> IronResult<Option<Response>> {
+        (**self).respond(req)
+    }
+
     fn catch(&self, req: &mut Request, err: IronError) -> IronResult<()> {
         (**self).catch(req, err)
     }
@@ -452,6 +1117,10 @@ where
         (**self).before(req)
     }
 
+    fn respond(&self, req: &mut Request) -> IronResult<Option<Response>> {
+        (**self).respond(req)
+    }
+
     fn catch(&self, req: &mut Request, err: IronError) -> IronResult<()> {
         (**self).catch(req, err)
     }
@@ -4,8 +4,12 @@ use std::sync::Arc;
 
 use self::Kind::{Fine, Prob};
 
+use futures::{future, Async as Poll, Future};
+
+use middleware::timing::{Stage, Timings};
+use middleware::{Async, AsyncOnly};
 use prelude::*;
-use {AfterMiddleware, BeforeMiddleware, Handler};
+use {AfterMiddleware, AsyncHandler, BeforeMiddleware, Handler, IronFuture, StatusCode};
 
 #[test]
 fn test_chain_normal() {
@@ -69,6 +73,316 @@ fn test_chain_handler_error_then_handle() {
     );
 }
 
+struct Cached;
+
+impl BeforeMiddleware for Cached {
+    fn respond(&self, _: &mut Request) -> IronResult<Option<Response>> {
+        Ok(Some(Response::with(StatusCode::NOT_MODIFIED)))
+    }
+}
+
+#[test]
+fn test_chain_before_respond() {
+    let (before, handler, after) = (sharedbool(false), sharedbool(false), sharedbool(false));
+    let (b, h, a) = (before.clone(), handler.clone(), after.clone());
+
+    let mut chain = Chain::new(move |_: &mut Request| {
+        h.store(true, Relaxed);
+        Ok(Response::with(StatusCode::OK))
+    });
+    chain.link_before(Cached);
+    chain.link_before(move |_: &mut Request| {
+        b.store(true, Relaxed);
+        Ok(())
+    });
+    chain.link_after(move |_: &mut Request, res| {
+        a.store(true, Relaxed);
+        Ok(res)
+    });
+
+    let res = chain.handle(&mut request()).unwrap();
+    assert_eq!(res.status, Some(StatusCode::NOT_MODIFIED));
+    assert!(!before.load(Relaxed));
+    assert!(!handler.load(Relaxed));
+    assert!(after.load(Relaxed));
+}
+
+#[test]
+fn test_async_chain_before_respond() {
+    let mut chain = Chain::new(Async(|_: &mut Request| -> IronFuture<Response> {
+        panic!("the handler should be skipped")
+    }));
+    chain.link_before(Cached);
+
+    let res = chain.handle_async(&mut request()).wait().unwrap();
+    assert_eq!(res.status, Some(StatusCode::NOT_MODIFIED));
+}
+
+#[test]
+fn test_chain_describe() {
+    fn hello(_: &mut Request) -> IronResult<Response> {
+        Ok(Response::new())
+    }
+
+    let mut chain = Chain::new(hello);
+    chain.link_before_named("auth", |_: &mut Request| Ok(()));
+    chain.link_before(Cached);
+    chain.link_around_named("inner", |h: Box<dyn Handler>| h);
+    chain.link_around_named("outer", |h: Box<dyn Handler>| h);
+    chain.link_after_named("headers", |_: &mut Request, res| Ok(res));
+
+    let description = chain.describe();
+    assert_eq!(
+        description.befores,
+        ["auth", "iron::middleware::test::Cached"]
+    );
+    assert_eq!(description.arounds, ["outer", "inner"]);
+    assert_eq!(
+        description.handler,
+        "iron::middleware::test::test_chain_describe::hello"
+    );
+    assert_eq!(description.afters, ["headers"]);
+    assert_eq!(
+        description.to_string(),
+        "before auth\n\
+         before iron::middleware::test::Cached\n\
+         around outer\n\
+         around inner\n\
+         handler iron::middleware::test::test_chain_describe::hello\n\
+         after headers\n"
+    );
+}
+
+#[test]
+fn test_chain_timings() {
+    let mut chain = Chain::new(|_: &mut Request| Err(error()));
+    chain.link_before_named("auth", |_: &mut Request| Ok(()));
+    let recover = into_middleware((Fine, &(sharedbool(false), sharedbool(false))));
+    chain.link_after_named("recover", recover);
+    chain.record_timings();
+
+    let mut req = request();
+    let res = chain.handle(&mut req).unwrap();
+    assert!(!res.headers.contains_key("server-timing"));
+
+    let stages = req
+        .extensions
+        .get::<Timings>()
+        .unwrap()
+        .iter()
+        .map(|timing| (timing.stage, &timing.name[..]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        stages,
+        [
+            (Stage::Before, "auth"),
+            (
+                Stage::Handler,
+                "iron::middleware::test::test_chain_timings::{{closure}}"
+            ),
+            (Stage::Catch, "recover"),
+        ]
+    );
+}
+
+#[test]
+fn test_chain_server_timing() {
+    let mut inner = Chain::new(|_: &mut Request| Ok(Response::new()));
+    inner.server_timing();
+    let mut chain = Chain::new(inner);
+    chain.link_before_named("auth", |_: &mut Request| Ok(()));
+    chain.server_timing();
+
+    let res = chain.handle(&mut request()).unwrap();
+    let values = res
+        .headers
+        .get_all("server-timing")
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(values.len(), 1);
+
+    let metrics = values[0].to_str().unwrap().split(", ").collect::<Vec<_>>();
+    assert_eq!(metrics.len(), 3);
+    assert!(metrics[0].starts_with("before-0;dur="));
+    assert!(metrics[1].starts_with("handler-1;dur="));
+    assert!(metrics[2].starts_with("handler-2;dur="));
+
+    chain.server_timing_names();
+    let res = chain.handle(&mut request()).unwrap();
+    let value = res.headers.get("server-timing").unwrap().to_str().unwrap();
+    let metrics = value.split(", ").collect::<Vec<_>>();
+    assert!(metrics[0].starts_with("before-0;desc=\"auth\";dur="));
+    assert!(metrics[2].starts_with("handler-2;desc=\"iron::middleware::Chain\";dur="));
+}
+
+#[test]
+fn test_async_chain_server_timing() {
+    let mut chain = Chain::new(Async(|_: &mut Request| -> IronFuture<Response> {
+        Box::new(future::poll_fn(|| Ok(Poll::Ready(Response::new()))))
+    }));
+    chain.link_before_named("auth", |_: &mut Request| Ok(()));
+    chain.server_timing();
+
+    let res = chain.handle_async(&mut request()).wait().unwrap();
+    let value = res.headers.get("server-timing").unwrap().to_str().unwrap();
+    assert!(value.starts_with("before-0;dur="));
+    assert!(value.contains(", handler-1;dur="));
+}
+
+#[test]
+fn test_async_chain() {
+    fn handler(_: &mut Request) -> IronFuture<Response> {
+        Box::new(future::poll_fn(|| {
+            Ok(Poll::Ready(Response::with(StatusCode::ACCEPTED)))
+        }))
+    }
+
+    let before = sharedbool(false);
+    let flag = before.clone();
+    let mut chain = Chain::new(Async(handler));
+    chain.link_before(move |_: &mut Request| {
+        flag.store(true, Relaxed);
+        Ok(())
+    });
+
+    let res = chain
+        .as_async()
+        .unwrap()
+        .handle_async(&mut request())
+        .wait()
+        .unwrap();
+    assert_eq!(res.status, Some(StatusCode::ACCEPTED));
+    assert!(before.load(Relaxed));
+
+    // After middleware run once the future completes.
+    let after = sharedbool(false);
+    let flag = after.clone();
+    chain.link_after(move |req: &mut Request, res| {
+        assert!(req.extensions.contains::<Marker>());
+        flag.store(true, Relaxed);
+        Ok(res)
+    });
+    let mut req = request();
+    req.extensions.insert::<Marker>(());
+    let (req, res) = chain
+        .as_async()
+        .unwrap()
+        .handle_owned(req)
+        .wait()
+        .ok()
+        .unwrap();
+    assert_eq!(res.status, Some(StatusCode::ACCEPTED));
+    assert!(after.load(Relaxed));
+    assert!(req.extensions.contains::<Marker>());
+}
+
+#[test]
+fn test_async_chain_after_catch() {
+    let mut chain = Chain::new(Async(|_: &mut Request| -> IronFuture<Response> {
+        Box::new(future::poll_fn(|| Err(error())))
+    }));
+    let recover = into_middleware((Fine, &(sharedbool(false), sharedbool(false))));
+    chain.link_after(recover);
+    chain.record_timings();
+
+    let (req, res) = chain.handle_owned(request()).wait().ok().unwrap();
+    assert_eq!(res.status, None);
+    let stages = req
+        .extensions
+        .get::<Timings>()
+        .unwrap()
+        .iter()
+        .map(|timing| timing.stage)
+        .collect::<Vec<_>>();
+    assert_eq!(stages, [Stage::Handler, Stage::Catch]);
+}
+
+#[test]
+fn test_async_chain_keeps_extensions() {
+    struct Tagged;
+
+    impl BeforeMiddleware for Tagged {
+        fn respond(&self, _: &mut Request) -> IronResult<Option<Response>> {
+            let mut res = Response::with(StatusCode::OK);
+            res.extensions.insert::<Marker>(());
+            Ok(Some(res))
+        }
+    }
+
+    let mut chain = Chain::new(Async(|_: &mut Request| -> IronFuture<Response> {
+        panic!("the handler should be skipped")
+    }));
+    chain.link_before(Tagged);
+    chain.link_after(|_: &mut Request, res: Response| {
+        assert!(res.extensions.contains::<Marker>());
+        Ok(res)
+    });
+
+    let (_, res) = chain.handle_owned(request()).wait().ok().unwrap();
+    assert!(res.extensions.contains::<Marker>());
+
+    let mut chain = Chain::new(Async(|_: &mut Request| -> IronFuture<Response> {
+        panic!("the handler should be skipped")
+    }));
+    chain.link_before(Tagged);
+    let res = chain.handle_async(&mut request()).wait().unwrap();
+    assert!(res.extensions.contains::<Marker>());
+}
+
+#[test]
+fn test_async_only() {
+    let mut chain = Chain::new(Async(|_: &mut Request| -> IronFuture<Response> {
+        Box::new(future::ok(Response::new()))
+    }));
+    let err = chain.handle(&mut request()).unwrap_err();
+    assert!(err.error.is::<AsyncOnly>());
+    assert_eq!(err.response.status, Some(StatusCode::INTERNAL_SERVER_ERROR));
+
+    // The future can't run the after middleware without the request.
+    chain.link_after(|_: &mut Request, res| Ok(res));
+    let err = chain.handle_async(&mut request()).wait().unwrap_err();
+    assert!(err.error.is::<AsyncOnly>());
+}
+
+#[test]
+fn test_async_chain_link_in_flight() {
+    let mut chain = Chain::new(Async(|_: &mut Request| -> IronFuture<Response> {
+        Box::new(future::ok(Response::with(StatusCode::ACCEPTED)))
+    }));
+    chain.link_after(|_: &mut Request, res| Ok(res));
+
+    let future = chain.handle_owned(request());
+    chain.link_after(|_: &mut Request, _| -> IronResult<Response> {
+        panic!("linked after the request started")
+    });
+    let (_, res) = future.wait().ok().unwrap();
+    assert_eq!(res.status, Some(StatusCode::ACCEPTED));
+    assert_eq!(chain.describe().afters.len(), 2);
+}
+
+struct Marker;
+
+impl ::typemap::Key for Marker {
+    type Value = ();
+}
+
+#[test]
+fn test_async_chain_before_error() {
+    let handled = sharedbool(false);
+    let flag = handled.clone();
+    let mut chain = Chain::new(Async(move |_: &mut Request| -> IronFuture<Response> {
+        flag.store(true, Relaxed);
+        Box::new(future::poll_fn(|| Ok(Poll::Ready(Response::new()))))
+    }));
+    chain.link_before(into_middleware((
+        Prob,
+        &(sharedbool(false), sharedbool(false)),
+    )));
+
+    assert!(chain.handle_async(&mut request()).wait().is_err());
+    assert!(!handled.load(Relaxed));
+}
+
 // Used to indicate the action taken by a middleware or handler.
 #[derive(Debug, PartialEq)]
 enum Kind {
@@ -201,27 +515,17 @@ fn to_chain(counters: &ChainLike<Twice<Arc<AtomicBool>>>, chain: ChainLike<Kind>
     let (befores, handler, afters) = chain;
     let (ref beforec, ref handlerc, ref afterc) = *counters;
 
-    let befores = befores
-        .into_iter()
-        .zip(beforec.iter())
-        .map(into_middleware)
-        .map(|m| Box::new(m) as Box<dyn BeforeMiddleware>)
-        .collect::<Vec<_>>();
-
-    let handler = into_middleware((handler, handlerc));
+    let mut chain = Chain::new(into_middleware((handler, handlerc)));
 
-    let afters = afters
-        .into_iter()
-        .zip(afterc.iter())
-        .map(into_middleware)
-        .map(|m| Box::new(m) as Box<dyn AfterMiddleware>)
-        .collect::<Vec<_>>();
+    for before in befores.into_iter().zip(beforec.iter()) {
+        chain.link_before(into_middleware(before));
+    }
 
-    Chain {
-        befores: befores,
-        handler: Some(Box::new(handler) as Box<dyn Handler>),
-        afters: afters,
+    for after in afters.into_iter().zip(afterc.iter()) {
+        chain.link_after(into_middleware(after));
     }
+
+    chain
 }
 
 fn into_middleware(input: (Kind, &Twice<Arc<AtomicBool>>)) -> Middleware {
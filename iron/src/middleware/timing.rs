@@ -0,0 +1,128 @@
+//! Per-stage timings of the request flow through a `Chain`.
+
+use std::fmt;
+use std::time::Duration;
+
+use headers::{HeaderName, HeaderValue};
+use typemap::Key;
+
+/// A stage of the request flow through a `Chain`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// The `before` method of a `BeforeMiddleware`.
+    Before,
+    /// The `Handler`, including any `AroundMiddleware`.
+    Handler,
+    /// The `after` method of an `AfterMiddleware`.
+    After,
+    /// The `catch` method of a `BeforeMiddleware` or `AfterMiddleware`.
+    Catch,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Stage::Before => "before",
+            Stage::Handler => "handler",
+            Stage::After => "after",
+            Stage::Catch => "catch",
+        })
+    }
+}
+
+/// How long a single middleware or handler took.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Timing {
+    /// The stage which was timed.
+    pub stage: Stage,
+    /// The name of the middleware or handler, as given by `Chain::describe`.
+    pub name: String,
+    /// How long the stage took.
+    pub duration: Duration,
+}
+
+/// The `Request` extension holding the timings recorded by a `Chain`, in
+/// the order the stages ran.
+pub struct Timings;
+
+impl Key for Timings {
+    type Value = Vec<Timing>;
+}
+
+// Marks the request as having its `Server-Timing` header written by the
+// outermost `Chain`, so nested chains don't write it again.
+pub(super) struct ServerTimingOwner;
+
+impl Key for ServerTimingOwner {
+    type Value = ();
+}
+
+pub(super) fn server_timing_name() -> HeaderName {
+    HeaderName::from_static("server-timing")
+}
+
+// Render timings as a `Server-Timing` header value.
+//
+// The metrics are named after their stage and numbered. Middleware names
+// aren't valid metric names, so with `names` they're sent as descriptions.
+pub(super) fn server_timing(timings: &[Timing], names: bool) -> Option<HeaderValue> {
+    let metrics = timings
+        .iter()
+        .enumerate()
+        .map(|(i, timing)| {
+            let mut metric = format!("{}-{}", timing.stage, i);
+            if names {
+                let desc = timing
+                    .name
+                    .chars()
+                    .map(|c| match c {
+                        '"' | '\\' => '_',
+                        c if c == ' ' || c.is_ascii_graphic() => c,
+                        _ => '?',
+                    })
+                    .collect::<String>();
+                metric.push_str(&format!(";desc=\"{}\"", desc));
+            }
+            let duration = timing.duration;
+            let millis = duration.as_secs() as f64 * 1e3 + f64::from(duration.subsec_nanos()) / 1e6;
+            metric.push_str(&format!(";dur={:.3}", millis));
+            metric
+        })
+        .collect::<Vec<_>>();
+
+    if metrics.is_empty() {
+        None
+    } else {
+        HeaderValue::from_str(&metrics.join(", ")).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_server_timing() {
+        let timings = vec![
+            Timing {
+                stage: Stage::Before,
+                name: "auth \"v2\"".to_owned(),
+                duration: Duration::from_micros(1500),
+            },
+            Timing {
+                stage: Stage::Handler,
+                name: "hello".to_owned(),
+                duration: Duration::from_millis(20),
+            },
+        ];
+        assert_eq!(
+            server_timing(&timings, false).unwrap(),
+            "before-0;dur=1.500, handler-1;dur=20.000"
+        );
+        assert_eq!(
+            server_timing(&timings, true).unwrap(),
+            "before-0;desc=\"auth _v2_\";dur=1.500, handler-1;desc=\"hello\";dur=20.000"
+        );
+        assert_eq!(server_timing(&[], true), None);
+    }
+}
@@ -40,6 +40,16 @@
 //! Response::with((StatusCode::FOUND, modifiers::Redirect(url)));
 //! ```
 //!
+//! The constructors on `Redirect` set the status code for you:
+//!
+//! ```
+//! # use iron::prelude::*;
+//! # use iron::modifiers;
+//! # use iron::Url;
+//! let url = Url::parse("http://doc.rust-lang.org").unwrap();
+//! Response::with(modifiers::Redirect::see_other(url));
+//! ```
+//!
 //! The modifiers are applied depending on their type. Currently the easiest
 //! way to see how different types are used as modifiers, take a look at [the
 //! source code](https://github.com/iron/iron/blob/master/iron/src/modifiers.rs).
@@ -47,18 +57,24 @@
 //! For more information about the modifier system, see
 //! [rust-modifier](https://github.com/reem/rust-modifier).
 
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::path::{Path, PathBuf};
 
 use modifier::Modifier;
+use typemap::Key;
 
 use mime::{self, Mime};
 
 use {headers, Request, Response, Set, StatusCode, Url};
 
+use futures::{Async, Poll, Stream};
+use headers::TypedHeader;
+use hyper::{Body, Chunk};
 use mime_guess;
-use response::{BodyReader, WriteBody};
+use response::{BodyFilter, BodyReader, WriteBody};
 
 impl Modifier<Response> for Mime {
     #[inline]
@@ -83,6 +99,113 @@ impl<R: io::Read + Send + 'static> Modifier<Response> for BodyReader<R> {
     }
 }
 
+/// Filter the body of the response through a `BodyFilter`.
+///
+/// The filter is applied while the body is written, so streaming bodies stay
+/// streaming and are filtered chunk by chunk:
+///
+/// ```
+/// use std::io::Write;
+/// use iron::modifiers::FilterBody;
+/// use iron::prelude::*;
+/// use iron::StatusCode;
+///
+/// let res = Response::with((StatusCode::OK, "hello"))
+///     .set(FilterBody(|chunk: &[u8], out: &mut dyn Write| {
+///         out.write_all(&chunk.to_ascii_uppercase())
+///     }));
+/// ```
+///
+/// Filters usually change the length of the body, so any `Content-Length`
+/// header is removed. Responses without a body are left unchanged.
+pub struct FilterBody<F>(pub F);
+
+impl<F: BodyFilter> Modifier<Response> for FilterBody<F> {
+    fn modify(self, res: &mut Response) {
+        if let Some(body) = res.body.take() {
+            res.headers.remove(headers::CONTENT_LENGTH);
+            res.body = Some(Box::new(FilteredBody {
+                body,
+                filter: Some(self.0),
+            }));
+        }
+    }
+}
+
+// A body written through a `BodyFilter`.
+struct FilteredBody<F> {
+    body: Box<dyn WriteBody>,
+    filter: Option<F>,
+}
+
+impl<F: BodyFilter> WriteBody for FilteredBody<F> {
+    fn write_body(&mut self, res: &mut dyn io::Write) -> io::Result<()> {
+        match self.filter {
+            Some(ref mut filter) => {
+                self.body
+                    .write_body(&mut FilterWriter { filter, out: res })?;
+                filter.finish(res)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn stream_body(&mut self) -> Option<Body> {
+        let body = self.body.stream_body()?;
+        let filter = self.filter.take()?;
+        Some(Body::wrap_stream(FilterStream {
+            body,
+            filter: Some(filter),
+        }))
+    }
+}
+
+// Passes everything written to it through a filter.
+struct FilterWriter<'a, F: 'a> {
+    filter: &'a mut F,
+    out: &'a mut dyn io::Write,
+}
+
+impl<'a, F: BodyFilter> io::Write for FilterWriter<'a, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.filter.filter(buf, self.out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// A streaming body passed through a filter chunk by chunk.
+struct FilterStream<F> {
+    body: Body,
+    filter: Option<F>,
+}
+
+impl<F: BodyFilter> Stream for FilterStream<F> {
+    type Item = Chunk;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll(&mut self) -> Poll<Option<Chunk>, Self::Error> {
+        let filter = match self.filter {
+            Some(ref mut filter) => filter,
+            None => return Ok(Async::Ready(None)),
+        };
+
+        let mut out = vec![];
+        match self.body.poll()? {
+            Async::NotReady => return Ok(Async::NotReady),
+            Async::Ready(Some(chunk)) => filter.filter(&chunk, &mut out)?,
+            Async::Ready(None) => {
+                filter.finish(&mut out)?;
+                self.filter = None;
+            }
+        }
+        Ok(Async::Ready(Some(Chunk::from(out))))
+    }
+}
+
 impl Modifier<Response> for String {
     #[inline]
     fn modify(self, res: &mut Response) {
@@ -128,13 +251,21 @@ impl Modifier<Response> for File {
 impl<'a> Modifier<Response> for &'a Path {
     /// Set the body to the contents of the File at this path.
     ///
-    /// ## Panics
-    ///
-    /// Panics if there is no file at the passed-in Path.
+    /// If the file can't be opened, the response is instead set to a
+    /// `404 Not Found`, `403 Forbidden` or `500 Internal Server Error` and
+    /// the error is returned by `Response::try_with`.
     fn modify(self, res: &mut Response) {
-        File::open(self)
-            .unwrap_or_else(|_| panic!("No such file: {}", self.display()))
-            .modify(res);
+        match File::open(self) {
+            Ok(file) => file.modify(res),
+            Err(e) => {
+                let status = match e.kind() {
+                    io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                    io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                return fail(res, FileError(self.to_owned(), e), status);
+            }
+        }
 
         let mime = mime_for_path(self);
         res.set_mut(mime);
@@ -144,9 +275,8 @@ impl<'a> Modifier<Response> for &'a Path {
 impl Modifier<Response> for PathBuf {
     /// Set the body to the contents of the File at this path.
     ///
-    /// ## Panics
-    ///
-    /// Panics if there is no file at the passed-in Path.
+    /// See the implementation for `&Path` for what happens if the file can't
+    /// be opened.
     #[inline]
     fn modify(self, res: &mut Response) {
         self.as_path().modify(res);
@@ -181,25 +311,371 @@ where
     }
 }
 
+/// A modifier for adding a header value to requests and responses, keeping
+/// any previous values.
+///
+/// This is useful for headers that can appear several times, such as
+/// `Set-Cookie` or `Link`.
+#[derive(Clone)]
+pub struct AppendHeader<H>(pub H, pub headers::HeaderValue);
+
+impl<H> Modifier<Response> for AppendHeader<H>
+where
+    H: headers::IntoHeaderName,
+{
+    fn modify(self, res: &mut Response) {
+        res.headers.append(self.0, self.1);
+    }
+}
+
+impl<H> Modifier<Request> for AppendHeader<H>
+where
+    H: headers::IntoHeaderName,
+{
+    fn modify(self, req: &mut Request) {
+        req.headers.append(self.0, self.1);
+    }
+}
+
+/// A modifier for removing every value of a header from requests and
+/// responses.
+#[derive(Clone)]
+pub struct RemoveHeader<H>(pub H);
+
+impl<H> Modifier<Response> for RemoveHeader<H>
+where
+    H: headers::AsHeaderName,
+{
+    fn modify(self, res: &mut Response) {
+        res.headers.remove(self.0);
+    }
+}
+
+impl<H> Modifier<Request> for RemoveHeader<H>
+where
+    H: headers::AsHeaderName,
+{
+    fn modify(self, req: &mut Request) {
+        req.headers.remove(self.0);
+    }
+}
+
+/// A modifier for merging values into a comma-separated header on requests
+/// and responses, such as `Vary` or `Cache-Control`.
+///
+/// The items of the new value are added to the existing ones, skipping any
+/// which are already present, and stored as a single header value.
+///
+/// ```
+/// # use iron::prelude::*;
+/// # use iron::headers;
+/// # use iron::modifiers::MergeHeader;
+/// let res = Response::with((
+///     MergeHeader(headers::VARY, headers::HeaderValue::from_static("Accept")),
+///     MergeHeader(headers::VARY, headers::HeaderValue::from_static("accept, Origin")),
+/// ));
+/// assert_eq!(res.headers.get(headers::VARY).unwrap(), "Accept, Origin");
+/// ```
+#[derive(Clone)]
+pub struct MergeHeader<H>(pub H, pub headers::HeaderValue);
+
+impl<H> Modifier<Response> for MergeHeader<H>
+where
+    H: headers::IntoHeaderName + headers::AsHeaderName + Clone,
+{
+    fn modify(self, res: &mut Response) {
+        merge_header(&mut res.headers, self.0, self.1);
+    }
+}
+
+impl<H> Modifier<Request> for MergeHeader<H>
+where
+    H: headers::IntoHeaderName + headers::AsHeaderName + Clone,
+{
+    fn modify(self, req: &mut Request) {
+        merge_header(&mut req.headers, self.0, self.1);
+    }
+}
+
+fn merge_header<H>(map: &mut headers::HeaderMap, name: H, value: headers::HeaderValue)
+where
+    H: headers::IntoHeaderName + headers::AsHeaderName + Clone,
+{
+    let merged = {
+        let values = map.get_all(name.clone()).iter().chain(Some(&value));
+        let mut items: Vec<&str> = vec![];
+        let mut valid = true;
+        for value in values {
+            match value.to_str() {
+                Ok(value) => {
+                    for item in value.split(',').map(|item| item.trim()) {
+                        if !item.is_empty() && !items.iter().any(|i| i.eq_ignore_ascii_case(item)) {
+                            items.push(item);
+                        }
+                    }
+                }
+                Err(_) => valid = false,
+            }
+        }
+        // Joining valid header values always produces a valid header value.
+        if valid {
+            Some(items.join(", ").parse().unwrap())
+        } else {
+            None
+        }
+    };
+
+    match merged {
+        Some(merged) => map.insert(name, merged),
+        // Values which aren't text can't be merged, so keep them apart.
+        None => {
+            map.append(name, value);
+            None
+        }
+    };
+}
+
+/// A modifier for adding several headers to requests and responses at once.
+///
+/// Each name and value pair is appended, so previous values of the headers
+/// are kept and names can be repeated.
+///
+/// ```
+/// # use iron::prelude::*;
+/// # use iron::headers;
+/// # use iron::modifiers::Headers;
+/// let res = Response::with(Headers(vec![
+///     (headers::SET_COOKIE, headers::HeaderValue::from_static("a=1")),
+///     (headers::SET_COOKIE, headers::HeaderValue::from_static("b=2")),
+/// ]));
+/// assert_eq!(res.headers.get_all(headers::SET_COOKIE).iter().count(), 2);
+/// ```
+#[derive(Clone)]
+pub struct Headers<I>(pub I);
+
+impl<I, H> Modifier<Response> for Headers<I>
+where
+    I: IntoIterator<Item = (H, headers::HeaderValue)>,
+    H: headers::IntoHeaderName,
+{
+    fn modify(self, res: &mut Response) {
+        for (name, value) in self.0 {
+            res.headers.append(name, value);
+        }
+    }
+}
+
+impl<I, H> Modifier<Request> for Headers<I>
+where
+    I: IntoIterator<Item = (H, headers::HeaderValue)>,
+    H: headers::IntoHeaderName,
+{
+    fn modify(self, req: &mut Request) {
+        for (name, value) in self.0 {
+            req.headers.append(name, value);
+        }
+    }
+}
+
+/// A modifier for setting typed headers on requests and responses.
+///
+/// Like `Header`, this replaces any previous values of the header.
+///
+/// If the header can't be encoded, a response is set to a `500 Internal
+/// Server Error` and the error is returned by `Response::try_with`, while
+/// a request is left unchanged.
+#[derive(Clone)]
+pub struct Typed<T>(pub T);
+
+impl<T: TypedHeader> Modifier<Response> for Typed<T> {
+    fn modify(self, res: &mut Response) {
+        if let Err(e) = res.set_typed(self.0) {
+            fail(res, e, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+}
+
+impl<T: TypedHeader> Modifier<Request> for Typed<T> {
+    fn modify(self, req: &mut Request) {
+        if let Err(e) = req.set_typed(self.0) {
+            error!("Couldn't set the {} header: {}", T::header_name(), e);
+        }
+    }
+}
+
 /// A modifier for creating redirect responses.
 pub struct Redirect(pub Url);
 
 impl Modifier<Response> for Redirect {
     fn modify(self, res: &mut Response) {
         let Redirect(url) = self;
-        // Url should always be parsable to a valid HeaderValue, so unwrap should be safe here.
-        res.headers
-            .insert(headers::LOCATION, url.to_string().parse().unwrap());
+        RedirectRaw(url.to_string()).modify(res);
     }
 }
 
 /// A modifier for creating redirect responses.
+///
+/// If the location is not a valid header value, the response is set to a
+/// `500 Internal Server Error` and the error is returned by
+/// `Response::try_with`.
 pub struct RedirectRaw(pub String);
 
 impl Modifier<Response> for RedirectRaw {
     fn modify(self, res: &mut Response) {
         let RedirectRaw(path) = self;
-        res.headers.insert(headers::LOCATION, path.parse().unwrap());
+        match path.parse() {
+            Ok(location) => {
+                res.headers.insert(headers::LOCATION, location);
+            }
+            Err(e) => fail(res, e, StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+
+impl Redirect {
+    /// Redirect permanently with `301 Moved Permanently`.
+    ///
+    /// Clients may change the method of the redirected request to `GET`.
+    pub fn permanent<L: ToString>(location: L) -> Redirection {
+        Redirection::new(StatusCode::MOVED_PERMANENTLY, location)
+    }
+
+    /// Redirect permanently with `308 Permanent Redirect`, which requires
+    /// clients to repeat the request with the same method and body.
+    pub fn permanent_preserve_method<L: ToString>(location: L) -> Redirection {
+        Redirection::new(StatusCode::PERMANENT_REDIRECT, location)
+    }
+
+    /// Redirect temporarily with `302 Found`.
+    ///
+    /// Clients may change the method of the redirected request to `GET`.
+    pub fn found<L: ToString>(location: L) -> Redirection {
+        Redirection::new(StatusCode::FOUND, location)
+    }
+
+    /// Redirect temporarily with `307 Temporary Redirect`, which requires
+    /// clients to repeat the request with the same method and body.
+    pub fn temporary<L: ToString>(location: L) -> Redirection {
+        Redirection::new(StatusCode::TEMPORARY_REDIRECT, location)
+    }
+
+    /// Redirect with `303 See Other`, which tells clients to fetch the
+    /// location with `GET`, e.g. after a form was posted.
+    pub fn see_other<L: ToString>(location: L) -> Redirection {
+        Redirection::new(StatusCode::SEE_OTHER, location)
+    }
+}
+
+/// A modifier for redirect responses which sets the status, the `Location`
+/// header and a short HTML body for clients which don't follow redirects.
+///
+/// Created by the constructors on `Redirect`:
+///
+/// ```
+/// # use iron::prelude::*;
+/// # use iron::modifiers::Redirect;
+/// # fn handler(req: &mut Request) -> IronResult<Response> {
+/// // Relative to the requested URL, e.g. `http://host/users/new`.
+/// Ok(Response::with(Redirect::see_other("../login").resolve(&req.url)))
+/// # }
+/// ```
+///
+/// If the location is not a valid header value, the response is set to a
+/// `500 Internal Server Error` and the error is returned by
+/// `Response::try_with`.
+#[derive(Clone, Debug)]
+pub struct Redirection {
+    status: StatusCode,
+    location: String,
+}
+
+impl Redirection {
+    fn new<L: ToString>(status: StatusCode, location: L) -> Redirection {
+        Redirection {
+            status,
+            location: location.to_string(),
+        }
+    }
+
+    /// Resolve a relative location against `base`, usually `Request::url`.
+    ///
+    /// Absolute locations are left unchanged.
+    pub fn resolve(mut self, base: &Url) -> Redirection {
+        if let Ok(resolved) = base.as_ref().join(&self.location) {
+            self.location = resolved.into_string();
+        }
+        self
+    }
+
+    /// The status code of this redirection.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The location this redirects to.
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+}
+
+impl Modifier<Response> for Redirection {
+    fn modify(self, res: &mut Response) {
+        let body = format!(
+            "<!DOCTYPE html>\n<html><head><title>{status}</title></head>\
+             <body><p>Redirecting to <a href=\"{location}\">{location}</a>.</p></body></html>\n",
+            status = self.status,
+            location = escape_html(&self.location),
+        );
+
+        res.set_mut((self.status, mime::TEXT_HTML_UTF_8, body));
+        RedirectRaw(self.location).modify(res);
+    }
+}
+
+pub(crate) fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The error of a modifier which could not be applied, stored in the
+/// `Response` until it is taken by `Response::try_with`.
+pub(crate) struct Failed;
+
+impl Key for Failed {
+    type Value = (Box<dyn Error + Send>, StatusCode);
+}
+
+fn fail<E: Error + Send + 'static>(res: &mut Response, err: E, status: StatusCode) {
+    res.status = Some(status);
+    res.body = None;
+    res.headers.remove(headers::CONTENT_LENGTH);
+    res.extensions.insert::<Failed>((Box::new(err), status));
+}
+
+/// The error recorded when the file given to a `Path` or `PathBuf` modifier
+/// can't be opened.
+#[derive(Debug)]
+pub struct FileError(pub PathBuf, pub io::Error);
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Couldn't open {}: {}", self.0.display(), self.1)
+    }
+}
+
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.1)
     }
 }
 
@@ -221,4 +697,180 @@ mod test {
         );
         assert_eq!(mime_for_path(Path::new("foo")), mime::TEXT_PLAIN);
     }
+
+    #[test]
+    fn test_missing_file() {
+        let path = Path::new("/this/file/does/not/exist.txt");
+
+        let res = Response::with((StatusCode::OK, path));
+        assert_eq!(res.status, Some(StatusCode::NOT_FOUND));
+        assert!(res.body.is_none());
+
+        let err = Response::try_with((StatusCode::OK, path)).err().unwrap();
+        assert_eq!(err.response.status, Some(StatusCode::NOT_FOUND));
+        assert!(err.error.is::<FileError>());
+    }
+
+    #[test]
+    fn test_missing_file_written_back() {
+        use futures::{Future, Stream};
+        use hyper::{Body, Method};
+        use response::HttpResponse;
+
+        let path = Path::new("/this/file/does/not/exist.txt");
+        let res = Response::with((path, StatusCode::OK));
+        assert_eq!(res.status, Some(StatusCode::OK));
+
+        let mut http_res = HttpResponse::new(Body::empty());
+        res.write_back(&mut http_res, Method::GET);
+        assert_eq!(http_res.status(), StatusCode::NOT_FOUND);
+        assert!(http_res.into_body().concat2().wait().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_redirect() {
+        let err = Response::try_with(RedirectRaw("/new\nline".to_owned()))
+            .err()
+            .unwrap();
+        assert_eq!(err.response.status, Some(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(err.response.headers.get(headers::LOCATION).is_none());
+
+        let res =
+            Response::try_with((StatusCode::FOUND, RedirectRaw("/elsewhere".to_owned()))).unwrap();
+        assert_eq!(res.headers.get(headers::LOCATION).unwrap(), "/elsewhere");
+    }
+
+    #[test]
+    fn test_invalid_typed_header() {
+        use headers::Location;
+
+        let err = Response::try_with(Typed(Location("/a\nb".to_owned())))
+            .err()
+            .unwrap();
+        assert_eq!(err.response.status, Some(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(err.response.headers.get(headers::LOCATION).is_none());
+    }
+
+    #[test]
+    fn test_redirection() {
+        let url = Url::parse("http://example.com/users/new?x=1").unwrap();
+
+        let mut res = Response::with(Redirect::permanent_preserve_method(url));
+        assert_eq!(res.status, Some(StatusCode::PERMANENT_REDIRECT));
+        assert_eq!(
+            res.headers.get(headers::LOCATION).unwrap(),
+            "http://example.com/users/new?x=1"
+        );
+        assert_eq!(
+            res.headers.get(headers::CONTENT_TYPE).unwrap(),
+            "text/html; charset=utf-8"
+        );
+
+        let mut body = vec![];
+        res.body.as_mut().unwrap().write_body(&mut body).unwrap();
+        assert!(String::from_utf8(body)
+            .unwrap()
+            .contains("<a href=\"http://example.com/users/new?x=1\">"));
+    }
+
+    #[test]
+    fn test_redirection_resolve() {
+        let base = Url::parse("http://example.com/users/new").unwrap();
+
+        let redirect = Redirect::see_other("../login?next=%2F").resolve(&base);
+        assert_eq!(redirect.status(), StatusCode::SEE_OTHER);
+        assert_eq!(redirect.location(), "http://example.com/login?next=%2F");
+
+        let redirect = Redirect::temporary("https://other.example/").resolve(&base);
+        assert_eq!(redirect.location(), "https://other.example/");
+    }
+
+    #[test]
+    fn test_append_and_remove_header() {
+        let mut res = Response::with((
+            Header(headers::LINK, "</a>; rel=next".parse().unwrap()),
+            AppendHeader(headers::LINK, "</b>; rel=prev".parse().unwrap()),
+        ));
+        assert_eq!(res.headers.get_all(headers::LINK).iter().count(), 2);
+
+        res.set_mut(RemoveHeader(headers::LINK));
+        assert!(res.headers.get(headers::LINK).is_none());
+    }
+
+    #[test]
+    fn test_merge_header() {
+        let mut req = Request::stub();
+        req.headers.append(headers::VARY, "Accept".parse().unwrap());
+        req.headers.append(headers::VARY, "Origin".parse().unwrap());
+
+        req.set_mut(MergeHeader(
+            headers::VARY,
+            "origin, Accept-Encoding".parse().unwrap(),
+        ));
+        let vary: Vec<_> = req.headers.get_all(headers::VARY).iter().collect();
+        assert_eq!(vary, vec!["Accept, Origin, Accept-Encoding"]);
+    }
+
+    #[test]
+    fn test_body_bytes() {
+        let mut res = Response::with((StatusCode::OK, "hello"));
+        assert_eq!(res.body_bytes().unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(res.body_bytes().unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(res.take_body_bytes().unwrap(), Some(b"hello".to_vec()));
+        assert!(res.body.is_none());
+        assert_eq!(res.take_body_bytes().unwrap(), None);
+    }
+
+    // Upper-cases the body and appends its length.
+    struct Shout(usize);
+
+    impl BodyFilter for Shout {
+        fn filter(&mut self, chunk: &[u8], out: &mut dyn io::Write) -> io::Result<()> {
+            self.0 += chunk.len();
+            out.write_all(&chunk.to_ascii_uppercase())
+        }
+
+        fn finish(&mut self, out: &mut dyn io::Write) -> io::Result<()> {
+            write!(out, " ({})", self.0)
+        }
+    }
+
+    struct Streamed(Vec<&'static str>);
+
+    impl WriteBody for Streamed {
+        fn write_body(&mut self, res: &mut dyn io::Write) -> io::Result<()> {
+            res.write_all(self.0.concat().as_bytes())
+        }
+
+        fn stream_body(&mut self) -> Option<Body> {
+            let chunks = self.0.drain(..).map(Ok::<_, io::Error>).collect::<Vec<_>>();
+            Some(Body::wrap_stream(::futures::stream::iter_result(chunks)))
+        }
+    }
+
+    #[test]
+    fn test_filter_body() {
+        let mut res = Response::with((StatusCode::OK, "hello")).set(FilterBody(Shout(0)));
+        assert!(res.headers.get(headers::CONTENT_LENGTH).is_none());
+        assert_eq!(res.body_bytes().unwrap(), Some(b"HELLO (5)".to_vec()));
+
+        let res = Response::with(StatusCode::NO_CONTENT).set(FilterBody(Shout(0)));
+        assert!(res.body.is_none());
+    }
+
+    #[test]
+    fn test_filter_streaming_body() {
+        use futures::Future;
+        use response::HttpResponse;
+        use Method;
+
+        let body: Box<dyn WriteBody> = Box::new(Streamed(vec!["hello, ", "world"]));
+        let res = Response::with((StatusCode::OK, body)).set(FilterBody(Shout(0)));
+
+        let mut http_res = HttpResponse::new(Body::empty());
+        res.write_back(&mut http_res, Method::GET);
+        let chunks = http_res.into_body().collect().wait().unwrap();
+        let chunks = chunks.iter().map(|c| &c[..]).collect::<Vec<_>>();
+        assert_eq!(chunks, vec![&b"HELLO, "[..], b"WORLD", b" (12)"]);
+    }
 }
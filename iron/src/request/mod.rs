@@ -20,7 +20,7 @@ use std::net::ToSocketAddrs;
 pub use self::url::Url;
 
 use error::HttpError;
-use headers::{self, HeaderMap};
+use headers::{self, HeaderMap, InvalidHeaderValue, TypedHeader};
 use {Plugin, Protocol, Set};
 
 mod url;
@@ -76,64 +76,33 @@ impl Request {
         local_addr: Option<SocketAddr>,
         protocol: &Protocol,
     ) -> Result<Request, String> {
-        let (
-            http::request::Parts {
-                method,
-                uri,
-                version,
-                headers,
-                ..
-            },
-            body,
-        ) = req.into_parts();
-
-        let url = {
-            let path = uri.path_and_query().expect("expected path and query but found None").as_str();
-
-            let query = uri.query();
-
-            let mut socket_ip = String::new();
-            let (host, port) = if let Some(host) = uri.host() {
-                (host, uri.port_part().map(|p| p.as_u16()))
-            } else if let Some(host) = headers.get(headers::HOST).and_then(|h| h.to_str().ok()) {
-                let mut parts = host.split(':');
-                let hostname = parts.next().unwrap();
-                let port = parts.next().and_then(|p| p.parse::<u16>().ok());
-                (hostname, port)
-            } else if version < HttpVersion::HTTP_11 {
-                if let Some(local_addr) = local_addr {
-                    match local_addr {
-                        SocketAddr::V4(addr4) => socket_ip.push_str(&format!("{}", addr4.ip())),
-                        SocketAddr::V6(addr6) => socket_ip.push_str(&format!("[{}]", addr6.ip())),
-                    }
-                    (socket_ip.as_ref(), Some(local_addr.port()))
-                } else {
-                    return Err("No fallback host specified".into());
-                }
-            } else {
-                return Err("No host specified in request".into());
-            };
-
-            let url_string = if let Some(port) = port {
-                if let Some(query) = query {
-                    format!("{}://{}:{}{}?{}", protocol.name(), host, port, path, query)
-                } else {
-                    format!("{}://{}:{}{}", protocol.name(), host, port, path)
-                }
-            } else {
-                if let Some(query) = query {
-                    format!("{}://{}{}?{}", protocol.name(), host, path, query)
-                } else {
-                    format!("{}://{}{}", protocol.name(), host, path)  
-                }
-            };
+        let (parts, body) = req.into_parts();
+        Request::from_parts(parts, body, local_addr, protocol).map_err(|(err, _)| err)
+    }
 
-            match Url::parse(&url_string) {
-                Ok(url) => url,
-                Err(e) => return Err(format!("Couldn't parse requested URL: {}", e)),
-            }
+    /// Create a request from the parts of an HttpRequest.
+    ///
+    /// If the request is invalid, the error is returned along with the
+    /// untouched parts, so it can still be answered. The parts are boxed to
+    /// keep the error small.
+    pub fn from_parts(
+        parts: http::request::Parts,
+        body: Body,
+        local_addr: Option<SocketAddr>,
+        protocol: &Protocol,
+    ) -> Result<Request, (String, Box<http::request::Parts>)> {
+        let url = match Request::url_of(&parts, local_addr, protocol) {
+            Ok(url) => url,
+            Err(err) => return Err((err, Box::new(parts))),
         };
 
+        let http::request::Parts {
+            method,
+            version,
+            headers,
+            ..
+        } = parts;
+
         Ok(Request {
             url,
             local_addr,
@@ -146,6 +115,57 @@ impl Request {
         })
     }
 
+    // The URL requested by an HttpRequest.
+    fn url_of(
+        parts: &http::request::Parts,
+        local_addr: Option<SocketAddr>,
+        protocol: &Protocol,
+    ) -> Result<Url, String> {
+        let (uri, headers, version) = (&parts.uri, &parts.headers, parts.version);
+
+        let path = uri.path();
+
+        let query = uri.query();
+
+        let mut socket_ip = String::new();
+        let (host, port) = if let Some(host) = uri.host() {
+            (host, uri.port_part().map(|p| p.as_u16()))
+        } else if let Some(host) = headers.get(headers::HOST).and_then(|h| h.to_str().ok()) {
+            let mut parts = host.split(':');
+            let hostname = parts.next().unwrap();
+            let port = parts.next().and_then(|p| p.parse::<u16>().ok());
+            (hostname, port)
+        } else if version < HttpVersion::HTTP_11 {
+            if let Some(local_addr) = local_addr {
+                match local_addr {
+                    SocketAddr::V4(addr4) => socket_ip.push_str(&format!("{}", addr4.ip())),
+                    SocketAddr::V6(addr6) => socket_ip.push_str(&format!("[{}]", addr6.ip())),
+                }
+                (socket_ip.as_ref(), Some(local_addr.port()))
+            } else {
+                return Err("No fallback host specified".into());
+            }
+        } else {
+            return Err("No host specified in request".into());
+        };
+
+        let url_string = if let Some(port) = port {
+            if let Some(query) = query {
+                format!("{}://{}:{}{}?{}", protocol.name(), host, port, path, query)
+            } else {
+                format!("{}://{}:{}{}", protocol.name(), host, port, path)
+            }
+        } else {
+            if let Some(query) = query {
+                format!("{}://{}{}?{}", protocol.name(), host, path, query)
+            } else {
+                format!("{}://{}{}", protocol.name(), host, path)
+            }
+        };
+
+        Url::parse(&url_string).map_err(|e| format!("Couldn't parse requested URL: {}", e))
+    }
+
     /// Get the contents of the body as a Vec<u8>
     ///
     /// This consumes the body future and turns it into Vec<u8>.  Note this should not be called
@@ -170,6 +190,50 @@ impl Request {
         Ok(self.extensions.get::<RequestBodyKey>().unwrap())
     }
 
+    /// Get a typed header of the request.
+    ///
+    /// Returns `None` if the header is missing or invalid.
+    pub fn typed_header<T: TypedHeader>(&self) -> Option<T> {
+        headers::get_typed(&self.headers)
+    }
+
+    /// Set a typed header on the request, replacing any previous values.
+    ///
+    /// Fails without changing the headers if the header holds a string
+    /// which isn't a valid header value.
+    pub fn set_typed<T: TypedHeader>(&mut self, header: T) -> Result<(), InvalidHeaderValue> {
+        headers::set_typed(&mut self.headers, header)
+    }
+
+    /// The id of the last Server-Sent Event received by the client, taken
+    /// from the `Last-Event-ID` header it sends when reconnecting.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.headers
+            .get("last-event-id")
+            .and_then(|id| id.to_str().ok())
+    }
+
+    // Create a request directly from its parts, for `testing`.
+    #[cfg(feature = "testing")]
+    pub(crate) fn with_parts(
+        method: Method,
+        url: Url,
+        headers: HeaderMap,
+        body: Body,
+        local_addr: Option<SocketAddr>,
+    ) -> Request {
+        Request {
+            url,
+            local_addr,
+            headers,
+            body: Some(body),
+            method,
+            extensions: TypeMap::new(),
+            version: HttpVersion::HTTP_11,
+            _p: (),
+        }
+    }
+
     #[cfg(test)]
     pub fn stub() -> Request {
         Request {
@@ -191,6 +255,18 @@ impl Key for RequestBodyKey {
     type Value = Vec<u8>;
 }
 
+/// The original, unmodified URL of a request whose `url` was rewritten by
+/// mounting middleware, stored in `Request::extensions`.
+///
+/// Handlers use it to build URLs pointing outside of the mounted path, such
+/// as redirects.
+#[derive(Copy, Clone)]
+pub struct OriginalUrl;
+
+impl Key for OriginalUrl {
+    type Value = Url;
+}
+
 // Allow plugins to attach to requests.
 impl Extensible for Request {
     fn extensions(&self) -> &TypeMap {
@@ -236,6 +312,24 @@ mod test {
         assert_eq!(iron_request.url.query(), Some("param=value"));
     }
 
+    #[test]
+    fn test_request_query_string_not_repeated() {
+        let mut hyper_request = HttpRequest::new(Body::empty());
+        *hyper_request.uri_mut() = "/path?param=value".parse().unwrap();
+        hyper_request
+            .headers_mut()
+            .insert(headers::HOST, "my-host:8080".parse().unwrap());
+
+        let iron_request = Request::from_http(hyper_request, None, &Protocol::http())
+            .expect("A valid Iron request");
+
+        assert_eq!(iron_request.url.path(), vec!["path"]);
+        assert_eq!(
+            iron_request.url.to_string(),
+            "http://my-host:8080/path?param=value"
+        );
+    }
+
     #[test]
     fn test_request_parse_host_header_only() {
         let mut hyper_request = HttpRequest::new(Body::empty());
@@ -8,7 +8,9 @@ use modifier::{Modifier, Set};
 use plugin::Extensible;
 use typemap::TypeMap;
 
-use {headers, Plugin, StatusCode};
+use headers::{InvalidHeaderValue, TypedHeader};
+use modifiers::Failed;
+use {headers, IronError, IronResult, Plugin, StatusCode};
 
 use hyper::Body;
 use hyper::Method;
@@ -21,6 +23,42 @@ pub struct BodyReader<R: Send>(pub R);
 pub trait WriteBody: Send {
     /// Writes the body to the provided `Write`.
     fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()>;
+
+    /// Take the body as a stream to be sent to the client while it is
+    /// being produced.
+    ///
+    /// Bodies are buffered through `write_body` by default. Long-lived
+    /// bodies, such as `sse::EventStream`, return `Some` here instead.
+    fn stream_body(&mut self) -> Option<Body> {
+        None
+    }
+}
+
+/// A filter over the body of a response, applied while it is written.
+///
+/// Set with the `modifiers::FilterBody` modifier. Implemented for closures
+/// taking each chunk of the body and the `Write` to send the filtered chunk
+/// to.
+pub trait BodyFilter: Send + 'static {
+    /// Filter a chunk of the body, writing the result to `out`.
+    ///
+    /// Chunks may be split anywhere, so filters looking for a pattern have to
+    /// buffer any incomplete match until the next chunk.
+    fn filter(&mut self, chunk: &[u8], out: &mut dyn Write) -> io::Result<()>;
+
+    /// Write anything left once the whole body has been filtered.
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F> BodyFilter for F
+where
+    F: Send + 'static + FnMut(&[u8], &mut dyn Write) -> io::Result<()>,
+{
+    fn filter(&mut self, chunk: &[u8], out: &mut dyn Write) -> io::Result<()> {
+        (*self)(chunk, out)
+    }
 }
 
 impl WriteBody for String {
@@ -107,17 +145,102 @@ impl Response {
     }
 
     /// Construct a Response with the specified modifier pre-applied.
+    ///
+    /// If a modifier fails, the response is sent with the status of the
+    /// failure, even if a later modifier sets another one. Use `try_with` to
+    /// handle the error instead.
     pub fn with<M: Modifier<Response>>(m: M) -> Response {
         Response::new().set(m)
     }
 
+    /// Construct a Response with the specified modifier pre-applied,
+    /// failing if the modifier could not be applied.
+    ///
+    /// Modifiers which can fail, such as `Path` when the file doesn't exist,
+    /// set an error status on the `Response` instead of panicking. This
+    /// returns that `Response` as an `IronError`, together with the cause.
+    pub fn try_with<M: Modifier<Response>>(m: M) -> IronResult<Response> {
+        Response::new().try_set(m)
+    }
+
+    /// Apply a modifier to this Response, failing if the modifier could not
+    /// be applied.
+    ///
+    /// See `try_with` for details.
+    pub fn try_set<M: Modifier<Response>>(mut self, m: M) -> IronResult<Response> {
+        self.set_mut(m);
+        match self.extensions.remove::<Failed>() {
+            Some((error, status)) => {
+                // Later modifiers may have overwritten the status of the failure.
+                self.status = Some(status);
+                Err(IronError {
+                    error,
+                    response: self,
+                })
+            }
+            None => Ok(self),
+        }
+    }
+
+    /// Get a typed header of the response.
+    ///
+    /// Returns `None` if the header is missing or invalid.
+    pub fn typed_header<T: TypedHeader>(&self) -> Option<T> {
+        headers::get_typed(&self.headers)
+    }
+
+    /// Set a typed header on the response, replacing any previous values.
+    ///
+    /// Fails without changing the headers if the header holds a string
+    /// which isn't a valid header value.
+    pub fn set_typed<T: TypedHeader>(&mut self, header: T) -> Result<(), InvalidHeaderValue> {
+        headers::set_typed(&mut self.headers, header)
+    }
+
+    /// Buffer the body of the response and return a copy of its bytes.
+    ///
+    /// The body is replaced by the buffered bytes, so the response is sent
+    /// unchanged. Returns `None` if the response has no body.
+    ///
+    /// Streaming bodies, such as `sse::EventStream`, are buffered until they
+    /// end. If writing the body fails, the response is left without a body.
+    pub fn body_bytes(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let bytes = self.take_body_bytes()?;
+        if let Some(ref bytes) = bytes {
+            self.body = Some(Box::new(bytes.clone()));
+        }
+        Ok(bytes)
+    }
+
+    /// Take the body out of the response as bytes, leaving it without a body.
+    ///
+    /// Returns `None` if the response has no body. A rewritten body can be
+    /// set again with a `Vec<u8>` or `String` modifier, which also updates
+    /// its `Content-Length`.
+    pub fn take_body_bytes(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.body.take() {
+            Some(mut body) => {
+                let mut bytes = vec![];
+                body.write_body(&mut bytes)?;
+                Ok(Some(bytes))
+            }
+            None => Ok(None),
+        }
+    }
+
     // `write_back` is used to put all the data added to `self`
     // back onto an `HttpResponse` so that it is sent back to the
     // client.
     //
     // `write_back` consumes the `Response`.
     #[doc(hidden)]
-    pub fn write_back(self, http_res: &mut HttpResponse<Body>, req_method: Method) {
+    pub fn write_back(mut self, http_res: &mut HttpResponse<Body>, req_method: Method) {
+        if let Some((error, status)) = self.extensions.remove::<Failed>() {
+            error!("Error applying modifier to response: {}", error);
+            // Later modifiers may have overwritten the status of the failure.
+            self.status = Some(status);
+        }
+
         *http_res.headers_mut() = self.headers;
 
         // Default to a 404 if no response code was set
@@ -126,6 +249,8 @@ impl Response {
         let out = match (self.body, req_method) {
             (Some(body), _) => write_with_body(http_res, body),
             (None, Method::HEAD) => Ok( () ),
+            // Informational responses, such as protocol upgrades, never have a body.
+            (None, _) if http_res.status().is_informational() => Ok(()),
             (None, _) => {
                 http_res.headers_mut().insert(
                     headers::CONTENT_LENGTH,
@@ -149,6 +274,11 @@ fn write_with_body(res: &mut HttpResponse<Body>, mut body: Box<dyn WriteBody>) -
     res.headers_mut()
         .insert(headers::CONTENT_TYPE, content_type);
 
+    if let Some(stream) = body.stream_body() {
+        *res.body_mut() = stream;
+        return Ok(());
+    }
+
     let mut body_contents: Vec<u8> = vec![];
     body.write_body(&mut body_contents)?;
     *res.body_mut() = Body::from(body_contents);
@@ -0,0 +1,406 @@
+//! Server-Sent Events response bodies.
+//!
+//! An `EventStream` is a response body which stays open and forwards events
+//! to the client as they are sent through its `EventSender`. The sender can
+//! be cloned and moved to other threads, so events can be pushed long after
+//! the handler has returned its `Response`.
+//!
+//! ```
+//! # use std::thread;
+//! # use iron::prelude::*;
+//! # use iron::StatusCode;
+//! use iron::sse::{Event, EventStream};
+//!
+//! fn handler(req: &mut Request) -> IronResult<Response> {
+//!     let resume_from = req.last_event_id().map(|id| id.to_owned());
+//!     let (stream, sender) = EventStream::new();
+//!
+//!     thread::spawn(move || {
+//!         let _ = sender.send(Event::new("hello").event("greeting").id("1"));
+//!         // The stream is closed once every sender has been dropped.
+//!     });
+//!
+//!     Ok(Response::with((StatusCode::OK, stream)))
+//! }
+//! ```
+//!
+//! The stream is closed when every `EventSender` has been dropped. Sending
+//! fails with `Disconnected` once the client has gone away.
+
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::Stream;
+use hyper::{Body, Chunk};
+
+use modifier::Modifier;
+use response::WriteBody;
+use {headers, Response};
+
+/// A single Server-Sent Event.
+///
+/// Multi-line data is split over several `data` fields, so it is received by
+/// the client unchanged.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    event: Option<String>,
+    data: String,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// Create an unnamed event carrying `data`.
+    pub fn new<D: Into<String>>(data: D) -> Event {
+        Event {
+            data: data.into(),
+            ..Event::default()
+        }
+    }
+
+    /// Set the event type, dispatched to `addEventListener` on the client.
+    pub fn event<E: Into<String>>(mut self, event: E) -> Event {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Set the event id, sent back by the client as `Last-Event-ID`
+    /// when it reconnects.
+    pub fn id<I: Into<String>>(mut self, id: I) -> Event {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Set the reconnection delay the client should use from now on.
+    pub fn retry(mut self, retry: Duration) -> Event {
+        self.retry = Some(retry);
+        self
+    }
+
+    fn to_frame(&self) -> String {
+        let mut frame = String::new();
+
+        if let Some(ref event) = self.event {
+            push_field(&mut frame, "event", event);
+        }
+        if let Some(ref id) = self.id {
+            push_field(&mut frame, "id", id);
+        }
+        if let Some(retry) = self.retry {
+            push_field(&mut frame, "retry", &millis(retry).to_string());
+        }
+        // Unlike `lines`, splitting keeps a trailing empty line, which the
+        // client turns back into a trailing newline.
+        for line in self.data.split('\n') {
+            push_field(&mut frame, "data", line);
+        }
+
+        frame.push('\n');
+        frame
+    }
+}
+
+// Field values may not contain line breaks, as they would end the field.
+fn push_field(frame: &mut String, name: &str, value: &str) {
+    frame.push_str(name);
+    frame.push_str(": ");
+    frame.extend(value.chars().filter(|&c| c != '\n' && c != '\r'));
+    frame.push('\n');
+}
+
+fn comment_frame(comment: &str) -> String {
+    let mut frame = String::new();
+    for line in comment.lines() {
+        frame.push_str(": ");
+        frame.push_str(line);
+        frame.push('\n');
+    }
+    if comment.is_empty() {
+        frame.push_str(":\n");
+    }
+    frame.push('\n');
+    frame
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+/// The error returned when sending to an `EventStream` whose client has
+/// disconnected.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Disconnected;
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("The event stream has been closed.")
+    }
+}
+
+impl Error for Disconnected {}
+
+/// A handle used to push events into an `EventStream`.
+///
+/// Senders can be cloned and sent between threads. The stream is closed
+/// once every sender has been dropped.
+#[derive(Clone)]
+pub struct EventSender {
+    inner: Arc<UnboundedSender<String>>,
+}
+
+impl EventSender {
+    /// Send an event to the client.
+    pub fn send(&self, event: Event) -> Result<(), Disconnected> {
+        self.send_frame(event.to_frame())
+    }
+
+    /// Send an unnamed event carrying only `data`.
+    pub fn send_data<D: Into<String>>(&self, data: D) -> Result<(), Disconnected> {
+        self.send(Event::new(data))
+    }
+
+    /// Send a comment, which is ignored by the client.
+    pub fn comment(&self, comment: &str) -> Result<(), Disconnected> {
+        self.send_frame(comment_frame(comment))
+    }
+
+    /// Set the reconnection delay of the client without dispatching an event.
+    pub fn retry(&self, retry: Duration) -> Result<(), Disconnected> {
+        self.send_frame(format!("retry: {}\n\n", millis(retry)))
+    }
+
+    /// Returns `true` if the client has disconnected.
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn send_frame(&self, frame: String) -> Result<(), Disconnected> {
+        self.inner.unbounded_send(frame).map_err(|_| Disconnected)
+    }
+}
+
+/// A `text/event-stream` response body.
+///
+/// Used as a modifier, it sets the body along with the `Content-Type` and
+/// `Cache-Control` headers event streams require.
+pub struct EventStream {
+    receiver: Option<UnboundedReceiver<String>>,
+    sender: Weak<UnboundedSender<String>>,
+    heartbeat: Option<Duration>,
+}
+
+impl EventStream {
+    /// Create a new event stream and the sender used to push events into it.
+    pub fn new() -> (EventStream, EventSender) {
+        let (tx, rx) = mpsc::unbounded();
+        let sender = EventSender {
+            inner: Arc::new(tx),
+        };
+        let stream = EventStream {
+            receiver: Some(rx),
+            sender: Arc::downgrade(&sender.inner),
+            heartbeat: None,
+        };
+        (stream, sender)
+    }
+
+    /// Send a comment to the client every `interval` while the stream is
+    /// open, so idle connections are not closed by proxies.
+    ///
+    /// The heartbeats of every stream are sent by a single shared thread.
+    /// Intervals shorter than 100ms are raised to 100ms, so they don't keep
+    /// that thread busy.
+    pub fn heartbeat(mut self, interval: Duration) -> EventStream {
+        self.heartbeat = Some(cmp::max(interval, MIN_HEARTBEAT));
+        self
+    }
+
+    fn start_heartbeat(&self) {
+        if let Some(interval) = self.heartbeat {
+            HEARTBEATS.add(self.sender.clone(), interval);
+        }
+    }
+}
+
+// The shortest interval between heartbeats.
+const MIN_HEARTBEAT: Duration = Duration::from_millis(100);
+
+lazy_static! {
+    static ref HEARTBEATS: Heartbeats = {
+        thread::spawn(|| HEARTBEATS.run());
+        Heartbeats {
+            streams: Mutex::new(Vec::new()),
+            added: Condvar::new(),
+        }
+    };
+}
+
+// The heartbeats of every event stream, sent by a single thread.
+struct Heartbeats {
+    streams: Mutex<Vec<Heartbeat>>,
+    added: Condvar,
+}
+
+struct Heartbeat {
+    // Only hold on to the sender weakly, so the heartbeat doesn't keep
+    // the stream open after every `EventSender` has been dropped.
+    sender: Weak<UnboundedSender<String>>,
+    interval: Duration,
+    due: Instant,
+}
+
+impl Heartbeats {
+    fn add(&self, sender: Weak<UnboundedSender<String>>, interval: Duration) {
+        self.streams.lock().unwrap().push(Heartbeat {
+            sender,
+            interval,
+            due: Instant::now() + interval,
+        });
+        self.added.notify_one();
+    }
+
+    fn run(&self) {
+        let mut streams = self.streams.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            // Send the heartbeats which are due, and forget closed streams.
+            let mut i = 0;
+            while i < streams.len() {
+                if streams[i].beat(now) {
+                    i += 1;
+                } else {
+                    streams.swap_remove(i);
+                }
+            }
+
+            streams = match streams.iter().map(|heartbeat| heartbeat.due).min() {
+                Some(due) => self.added.wait_timeout(streams, due - now).unwrap().0,
+                None => self.added.wait(streams).unwrap(),
+            };
+        }
+    }
+}
+
+impl Heartbeat {
+    // Send the heartbeat if it's due. Returns whether the stream is still
+    // open.
+    fn beat(&mut self, now: Instant) -> bool {
+        if self.due > now {
+            return self.sender.strong_count() > 0;
+        }
+        self.due = now + self.interval;
+        match self.sender.upgrade() {
+            Some(sender) => sender.unbounded_send(comment_frame("")).is_ok(),
+            None => false,
+        }
+    }
+}
+
+impl WriteBody for EventStream {
+    fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()> {
+        // Buffering an event stream blocks until every sender is dropped.
+        if let Some(receiver) = self.receiver.take() {
+            self.start_heartbeat();
+            for frame in receiver.wait().flatten() {
+                res.write_all(frame.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stream_body(&mut self) -> Option<Body> {
+        self.receiver.take().map(|receiver| {
+            self.start_heartbeat();
+            Body::wrap_stream(
+                receiver
+                    .map(Chunk::from)
+                    .map_err(|()| io::Error::new(io::ErrorKind::Other, "event stream failed")),
+            )
+        })
+    }
+}
+
+impl Modifier<Response> for EventStream {
+    fn modify(self, res: &mut Response) {
+        res.headers.insert(
+            headers::CONTENT_TYPE,
+            headers::HeaderValue::from_static("text/event-stream"),
+        );
+        res.headers.insert(
+            headers::CACHE_CONTROL,
+            headers::HeaderValue::from_static("no-cache"),
+        );
+        res.headers.remove(headers::CONTENT_LENGTH);
+        res.body = Some(Box::new(self));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::Future;
+    use hyper::Method;
+    use response::HttpResponse;
+
+    #[test]
+    fn test_event_frame() {
+        let event = Event::new("first\nsecond")
+            .event("update")
+            .id("42")
+            .retry(Duration::from_millis(1500));
+        assert_eq!(
+            event.to_frame(),
+            "event: update\nid: 42\nretry: 1500\ndata: first\ndata: second\n\n"
+        );
+        assert_eq!(Event::new("").to_frame(), "data: \n\n");
+        assert_eq!(Event::new("line\n").to_frame(), "data: line\ndata: \n\n");
+        assert_eq!(Event::new("a\r\nb").to_frame(), "data: a\ndata: b\n\n");
+        assert_eq!(comment_frame(""), ":\n\n");
+    }
+
+    #[test]
+    fn test_stream_is_written_back() {
+        let (stream, sender) = EventStream::new();
+        let mut http_res = HttpResponse::new(Body::empty());
+        Response::with(stream).write_back(&mut http_res, Method::GET);
+
+        assert_eq!(
+            http_res.headers().get(headers::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+
+        sender.send_data("hello").unwrap();
+        sender.comment("ping").unwrap();
+        drop(sender);
+
+        let body = http_res.into_body().concat2().wait().unwrap();
+        assert_eq!(&*body, &b"data: hello\n\n: ping\n\n"[..]);
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let (stream, sender) = EventStream::new();
+        let mut stream = stream.heartbeat(Duration::from_millis(0));
+        assert_eq!(stream.heartbeat, Some(MIN_HEARTBEAT));
+        let body = stream.stream_body().unwrap();
+
+        let (heartbeat, _) = body.into_future().wait().ok().unwrap();
+        assert_eq!(&*heartbeat.unwrap(), &b":\n\n"[..]);
+        drop(sender);
+    }
+
+    #[test]
+    fn test_send_after_disconnect() {
+        let (stream, sender) = EventStream::new();
+        drop(stream);
+        assert!(sender.is_closed());
+        assert_eq!(sender.send_data("lost"), Err(Disconnected));
+    }
+}
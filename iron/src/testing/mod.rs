@@ -0,0 +1,254 @@
+//! Utilities for testing handlers and middleware.
+//!
+//! Enabled by the `testing` feature. A `RequestBuilder` creates a `Request`
+//! and runs any `Handler` against it in-process, without binding a port:
+//!
+//! ```
+//! use iron::prelude::*;
+//! use iron::testing;
+//! use iron::StatusCode;
+//!
+//! fn hello(req: &mut Request) -> IronResult<Response> {
+//!     let name = req.url.query().unwrap_or("world").to_owned();
+//!     Ok(Response::with((StatusCode::OK, format!("Hello, {}!", name))))
+//! }
+//!
+//! let res = testing::get("/hello?iron").handle(&hello);
+//! assert_eq!(res.status(), StatusCode::OK);
+//! assert_eq!(res.text(), "Hello, iron!");
+//! ```
+//!
+//! End-to-end tests can serve a whole `Iron` over loopback with a
+//! `TestServer` instead.
+
+use std::error::Error;
+use std::net::SocketAddr;
+
+use futures::{Future, Stream};
+use hyper::Body;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use headers::{self, HeaderMap, HeaderName, HeaderValue};
+use response::HttpResponse;
+use {Handler, Method, Request, Response, StatusCode, Url};
+
+pub use self::server::TestServer;
+
+mod server;
+
+/// Start building a request with the given method.
+///
+/// `url` may be a path, which is requested from `http://localhost`.
+pub fn request(method: Method, url: &str) -> RequestBuilder {
+    RequestBuilder {
+        method,
+        url: url.to_owned(),
+        headers: HeaderMap::new(),
+        body: vec![],
+        local_addr: None,
+    }
+}
+
+/// Start building a `GET` request.
+pub fn get(url: &str) -> RequestBuilder {
+    request(Method::GET, url)
+}
+
+/// Start building a `HEAD` request.
+pub fn head(url: &str) -> RequestBuilder {
+    request(Method::HEAD, url)
+}
+
+/// Start building a `POST` request.
+pub fn post(url: &str) -> RequestBuilder {
+    request(Method::POST, url)
+}
+
+/// Start building a `PUT` request.
+pub fn put(url: &str) -> RequestBuilder {
+    request(Method::PUT, url)
+}
+
+/// Start building a `PATCH` request.
+pub fn patch(url: &str) -> RequestBuilder {
+    request(Method::PATCH, url)
+}
+
+/// Start building a `DELETE` request.
+pub fn delete(url: &str) -> RequestBuilder {
+    request(Method::DELETE, url)
+}
+
+/// A builder for `Request`s used in tests.
+#[derive(Debug, Clone)]
+pub struct RequestBuilder {
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    local_addr: Option<SocketAddr>,
+}
+
+impl RequestBuilder {
+    /// Append a header to the request.
+    ///
+    /// Panics if the header name or value is invalid.
+    pub fn header(mut self, name: &str, value: &str) -> RequestBuilder {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid header name");
+        let value = HeaderValue::from_str(value).expect("invalid header value");
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Set the body of the request.
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> RequestBuilder {
+        self.body = body.into();
+        self
+    }
+
+    /// Set the body of the request to `value` serialized as JSON, along with
+    /// its `Content-Type`.
+    pub fn json<T: Serialize>(mut self, value: &T) -> RequestBuilder {
+        self.body = serde_json::to_vec(value).expect("value could not be serialized");
+        self.headers.insert(
+            headers::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        self
+    }
+
+    /// Set the local address the request was received on.
+    pub fn local_addr(mut self, addr: SocketAddr) -> RequestBuilder {
+        self.local_addr = Some(addr);
+        self
+    }
+
+    /// Build the `Request`.
+    ///
+    /// Panics if the URL is invalid.
+    pub fn build(self) -> Request {
+        let url = self.url("http://localhost");
+        let headers = self.headers_with_length();
+        Request::with_parts(
+            self.method,
+            url,
+            headers,
+            Body::from(self.body),
+            self.local_addr,
+        )
+    }
+
+    /// Build the request and run `handler` against it.
+    ///
+    /// Errors are turned into their response, as `Iron` does.
+    pub fn handle<H: Handler>(self, handler: &H) -> TestResponse {
+        let mut req = self.build();
+        match handler.handle(&mut req) {
+            Ok(res) => TestResponse::new(res, &req.method, None),
+            Err(err) => TestResponse::new(err.response, &req.method, Some(err.error)),
+        }
+    }
+}
+
+impl RequestBuilder {
+    // The URL of the request, with paths resolved against `base`.
+    fn url(&self, base: &str) -> Url {
+        let url = if self.url.starts_with('/') {
+            format!("{}{}", base, self.url)
+        } else {
+            self.url.clone()
+        };
+        Url::parse(&url).expect("invalid request URL")
+    }
+
+    fn headers_with_length(&self) -> HeaderMap {
+        let mut headers = self.headers.clone();
+        if !self.body.is_empty() && !headers.contains_key(headers::CONTENT_LENGTH) {
+            headers.insert(headers::CONTENT_LENGTH, self.body.len().into());
+        }
+        headers
+    }
+}
+
+/// A `Response` written back as it would be sent to the client.
+#[derive(Debug)]
+pub struct TestResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    error: Option<Box<dyn Error + Send>>,
+}
+
+impl TestResponse {
+    /// Write back `res` as the response to a request with `method`,
+    /// reading the whole body.
+    ///
+    /// Streaming bodies are read until they end.
+    pub fn from_response(res: Response, method: &Method) -> TestResponse {
+        TestResponse::new(res, method, None)
+    }
+
+    fn new(res: Response, method: &Method, error: Option<Box<dyn Error + Send>>) -> TestResponse {
+        let mut http_res = HttpResponse::new(Body::empty());
+        res.write_back(&mut http_res, method.clone());
+        // The server never sends the body of a response to `HEAD`.
+        if *method == Method::HEAD {
+            *http_res.body_mut() = Body::empty();
+        }
+        TestResponse::from_http(http_res, error)
+    }
+
+    pub(crate) fn from_http(
+        http_res: HttpResponse<Body>,
+        error: Option<Box<dyn Error + Send>>,
+    ) -> TestResponse {
+        let (parts, body) = http_res.into_parts();
+        let body = body.concat2().wait().expect("failed to read the body");
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: body.to_vec(),
+            error,
+        }
+    }
+
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The first value of a header, if it is present and valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// The body of the response.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The body of the response as text, replacing invalid UTF-8.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Deserialize the body of the response from JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_slice(&self.body)
+    }
+
+    /// The error returned by the handler, if any.
+    pub fn error(&self) -> Option<&(dyn Error + Send)> {
+        self.error.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test;
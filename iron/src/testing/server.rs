@@ -0,0 +1,204 @@
+//! An `Iron` served over loopback, with a minimal HTTP/1.1 client.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use futures::sync::oneshot;
+use futures::Future;
+use httparse;
+use hyper::Body;
+
+use super::{RequestBuilder, TestResponse};
+use headers::{self, HeaderName, HeaderValue};
+use response::HttpResponse;
+use url::Url;
+use {Handler, Iron, StatusCode};
+
+/// An `Iron` listening on an ephemeral port of `127.0.0.1`.
+///
+/// The server runs on its own thread until the `TestServer` is dropped:
+///
+/// ```
+/// use iron::prelude::*;
+/// use iron::testing::{self, TestServer};
+/// use iron::StatusCode;
+///
+/// let server = TestServer::new(Iron::new(|_: &mut Request| {
+///     Ok(Response::with((StatusCode::OK, "Hello, world!")))
+/// }));
+///
+/// let res = server.send(testing::get("/"));
+/// assert_eq!(res.status(), StatusCode::OK);
+/// assert_eq!(res.text(), "Hello, world!");
+/// ```
+pub struct TestServer {
+    addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    /// Start serving `iron` on an ephemeral port.
+    ///
+    /// Panics if no port can be bound.
+    pub fn new<H: Handler>(mut iron: Iron<H>) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind the test server");
+        let addr = listener
+            .local_addr()
+            .expect("failed to bind the test server");
+        iron.local_address = Some(addr);
+
+        let (shutdown, signal) = oneshot::channel();
+        let thread = thread::spawn(move || iron.listen(listener, signal.map_err(|_| ())));
+
+        TestServer {
+            addr,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        }
+    }
+
+    /// The address the server is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The base URL of the server, such as `http://127.0.0.1:43210`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Send a request to the server and read the whole response.
+    ///
+    /// Paths are requested from the server itself. Absolute URLs are sent to
+    /// the server too, with the `Host` header of their own host.
+    ///
+    /// Panics if the request can't be sent or the response can't be read.
+    pub fn send(&self, req: RequestBuilder) -> TestResponse {
+        let http_res = self
+            .exchange(&req)
+            .unwrap_or_else(|e| panic!("test request failed: {}", e));
+        TestResponse::from_http(http_res, None)
+    }
+
+    fn exchange(&self, req: &RequestBuilder) -> io::Result<HttpResponse<Body>> {
+        let url: Url = req.url(&self.url()).into();
+        let mut target = url.path().to_owned();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+
+        let mut headers = req.headers_with_length();
+        if !headers.contains_key(headers::HOST) {
+            let host = match (url.host_str(), url.port()) {
+                (Some(host), Some(port)) => format!("{}:{}", host, port),
+                (Some(host), None) => host.to_owned(),
+                (None, _) => self.addr.to_string(),
+            };
+            headers.insert(
+                headers::HOST,
+                HeaderValue::from_str(&host).map_err(invalid)?,
+            );
+        }
+        // Each request uses its own connection, so the server is left without
+        // any once the response has been read.
+        headers.insert(headers::CONNECTION, HeaderValue::from_static("close"));
+
+        let mut head = format!("{} {} HTTP/1.1\r\n", req.method, target).into_bytes();
+        for (name, value) in &headers {
+            head.extend_from_slice(name.as_str().as_bytes());
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(value.as_bytes());
+            head.extend_from_slice(b"\r\n");
+        }
+        head.extend_from_slice(b"\r\n");
+
+        let mut stream = TcpStream::connect(self.addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+        stream.write_all(&head)?;
+        stream.write_all(&req.body)?;
+
+        let mut stream = BufReader::new(stream);
+        let mut res = read_head(&mut stream)?;
+
+        let chunked = res
+            .headers()
+            .get(headers::TRANSFER_ENCODING)
+            .map_or(false, |value| {
+                value.as_bytes().eq_ignore_ascii_case(b"chunked")
+            });
+        let body = if chunked {
+            read_chunked(&mut stream)?
+        } else {
+            let mut body = vec![];
+            stream.read_to_end(&mut body)?;
+            body
+        };
+        *res.body_mut() = Body::from(body);
+        Ok(res)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Read the status line and headers of a response.
+fn read_head<R: BufRead>(stream: &mut R) -> io::Result<HttpResponse<Body>> {
+    let mut head = vec![];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read_until(b'\n', &mut head)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed before the response",
+            ));
+        }
+    }
+
+    let mut parsed = [httparse::EMPTY_HEADER; 64];
+    let mut parsed = httparse::Response::new(&mut parsed);
+    parsed.parse(&head).map_err(invalid)?;
+
+    let mut res = HttpResponse::new(Body::empty());
+    *res.status_mut() = StatusCode::from_u16(parsed.code.unwrap_or(0)).map_err(invalid)?;
+    for header in parsed.headers.iter() {
+        let name = HeaderName::from_bytes(header.name.as_bytes()).map_err(invalid)?;
+        let value = HeaderValue::from_bytes(header.value).map_err(invalid)?;
+        res.headers_mut().append(name, value);
+    }
+    Ok(res)
+}
+
+// Read a body sent with `Transfer-Encoding: chunked`, ignoring trailers.
+fn read_chunked<R: BufRead>(stream: &mut R) -> io::Result<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line)?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(invalid)?;
+        if size == 0 {
+            return Ok(body);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        stream.read_exact(&mut body[start..])?;
+        let mut crlf = [0; 2];
+        stream.read_exact(&mut crlf)?;
+    }
+}
+
+fn invalid<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
@@ -0,0 +1,199 @@
+use std::io::{Read, Write};
+
+use futures::{future, Future, Stream};
+use serde_json::Value;
+
+use super::*;
+use error::ErrorResponses;
+use middleware::Async;
+use prelude::*;
+use response::BodyReader;
+use IronFuture;
+
+fn echo(req: &mut Request) -> IronResult<Response> {
+    let body = req.body.take().unwrap().concat2().wait().unwrap();
+    let content_type = req
+        .headers
+        .get(headers::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_owned())
+        .unwrap_or_default();
+    Ok(Response::with((
+        StatusCode::OK,
+        format!(
+            "{} {} {} {}",
+            req.method,
+            req.url,
+            content_type,
+            String::from_utf8(body.to_vec()).unwrap()
+        ),
+    )))
+}
+
+#[test]
+fn test_request_builder() {
+    let req = post("/users?page=2")
+        .header("x-request-id", "42")
+        .header("x-request-id", "43")
+        .body("hello")
+        .build();
+    assert_eq!(req.method, Method::POST);
+    assert_eq!(req.url.to_string(), "http://localhost/users?page=2");
+    assert_eq!(req.url.query(), Some("page=2"));
+    assert_eq!(req.headers.get_all("x-request-id").iter().count(), 2);
+    assert_eq!(req.headers[headers::CONTENT_LENGTH], "5");
+
+    let req = get("https://example.com:8443/").build();
+    assert_eq!(req.url.port(), 8443);
+}
+
+#[test]
+fn test_handle() {
+    let res = put("/echo").body("data").handle(&echo);
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text(), "PUT http://localhost/echo  data");
+    assert_eq!(res.body(), &b"PUT http://localhost/echo  data"[..]);
+    assert_eq!(res.header("content-length"), Some("31"));
+    assert!(res.error().is_none());
+}
+
+#[test]
+fn test_json() {
+    let value: Value = ::serde_json::from_str(r#"{"name":"iron"}"#).unwrap();
+    let res = post("/").json(&value).handle(&echo);
+    assert_eq!(
+        res.text(),
+        r#"POST http://localhost/ application/json {"name":"iron"}"#
+    );
+
+    let res =
+        get("/").handle(&|_: &mut Request| Ok(Response::with((StatusCode::OK, r#"{"id":1}"#))));
+    let body: Value = res.json().unwrap();
+    assert_eq!(body["id"], 1);
+    assert!(res.json::<Vec<u8>>().is_err());
+}
+
+#[test]
+fn test_handle_error() {
+    let handler = |_: &mut Request| -> IronResult<Response> {
+        let err = ::std::io::Error::from(::std::io::ErrorKind::NotFound);
+        Err(IronError::new(err, StatusCode::SERVICE_UNAVAILABLE))
+    };
+    let res = delete("/").handle(&handler);
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.error().unwrap().to_string(), "entity not found");
+
+    let mut chain = Chain::new(handler);
+    chain.link_after(ErrorResponses::new());
+    let res = delete("/").handle(&chain);
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[test]
+fn test_head_has_no_body() {
+    let res = head("/").handle(&|_: &mut Request| Ok(Response::with((StatusCode::OK, "body"))));
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.body().is_empty());
+}
+
+#[test]
+fn test_server() {
+    let server = TestServer::new(Iron::new(echo));
+    assert_eq!(server.url(), format!("http://{}", server.addr()));
+
+    let res = server.send(
+        post("/echo?x=1")
+            .header("content-type", "text/plain")
+            .body("hi"),
+    );
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.text(),
+        format!("POST {}/echo?x=1 text/plain hi", server.url())
+    );
+
+    let res = server.send(get("http://example.com/"));
+    assert_eq!(res.text(), "GET http://example.com/  ");
+}
+
+#[test]
+fn test_server_streaming_body() {
+    let server = TestServer::new(Iron::new(|_: &mut Request| {
+        let body = BodyReader(::std::io::Cursor::new(vec![b'a'; 100_000]));
+        let mut res = Response::with((StatusCode::OK, body));
+        res.headers.insert(
+            headers::TRANSFER_ENCODING,
+            HeaderValue::from_static("chunked"),
+        );
+        Ok(res)
+    }));
+
+    let res = server.send(get("/"));
+    assert_eq!(res.header("transfer-encoding"), Some("chunked"));
+    assert_eq!(res.body(), &vec![b'a'; 100_000][..]);
+
+    let res = server.send(head("/"));
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.body().is_empty());
+}
+
+#[test]
+fn test_server_async_chain() {
+    let mut chain = Chain::new(Async(|req: &mut Request| -> IronFuture<Response> {
+        let path = req.url.path().join("/");
+        Box::new(future::poll_fn(move || {
+            Ok(::futures::Async::Ready(Response::with((
+                StatusCode::OK,
+                &path[..],
+            ))))
+        }))
+    }));
+    chain.link_after(|req: &mut Request, mut res: Response| {
+        let method = HeaderValue::from_str(req.method.as_str()).unwrap();
+        res.headers.insert(headers::ALLOW, method);
+        Ok(res)
+    });
+    let server = TestServer::new(Iron::new(chain));
+
+    let res = server.send(get("/a/b"));
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.header("allow"), Some("GET"));
+    assert_eq!(res.text(), "a/b");
+}
+
+#[test]
+fn test_server_shuts_down_on_drop() {
+    let server = TestServer::new(Iron::new(echo));
+    let addr = server.addr();
+    server.send(get("/"));
+    drop(server);
+    assert!(::std::net::TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn test_server_uses_iron_settings() {
+    let mut iron = Iron::new(echo);
+    iron.max_buf_size = Some(8192);
+    iron.default_headers
+        .insert(headers::SERVER, HeaderValue::from_static("iron"));
+    let server = TestServer::new(iron);
+
+    let res = server.send(get("/"));
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.header("server"), Some("iron"));
+
+    // The server refuses the head, which may reset the connection before the
+    // response is read.
+    let mut stream = ::std::net::TcpStream::connect(server.addr()).unwrap();
+    stream
+        .set_read_timeout(Some(::std::time::Duration::from_secs(30)))
+        .unwrap();
+    let head = format!(
+        "GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\nx-large: {}\r\n\r\n",
+        "a".repeat(16384)
+    );
+    let mut res = String::new();
+    let _ = stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.read_to_string(&mut res));
+    assert!(!res.starts_with("HTTP/1.1 200"));
+}
@@ -0,0 +1,750 @@
+//! WebSocket upgrades from Iron handlers.
+//!
+//! A `Handler` accepts a WebSocket by validating the handshake with
+//! `WebSocketUpgrade::from_request` and responding with the modifier
+//! returned by `WebSocketUpgrade::accept`. Once the `101 Switching Protocols`
+//! response has been sent, the callback passed to `accept` is run on its own
+//! thread with a `WebSocket` to exchange messages with the client.
+//!
+//! ```
+//! # use iron::prelude::*;
+//! use iron::websocket::{Message, WebSocketUpgrade};
+//!
+//! fn echo(req: &mut Request) -> IronResult<Response> {
+//!     let upgrade = WebSocketUpgrade::from_request(req)?.protocol("echo");
+//!
+//!     Ok(Response::with(upgrade.accept(|mut socket| {
+//!         while let Ok(message) = socket.recv() {
+//!             match message {
+//!                 Message::Close(_) => break,
+//!                 message => { let _ = socket.send(message); }
+//!             }
+//!         }
+//!     })))
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::mpsc as std_mpsc;
+use std::thread;
+
+use base64;
+use futures::sync::mpsc::{self, UnboundedSender};
+use futures::{future, Future, Stream};
+use hyper;
+use hyper::upgrade::{OnUpgrade, Upgraded};
+use sha1::Sha1;
+use tokio_io::io as async_io;
+use tokio_io::AsyncRead;
+
+use http::version::Version as HttpVersion;
+use modifier::Modifier;
+use typemap::Key;
+use {headers, IronError, Method, Request, Response, StatusCode};
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const MAX_MESSAGE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The error returned when a request is not a valid WebSocket handshake.
+///
+/// It converts into an `IronError` with a `400 Bad Request` response, or
+/// `426 Upgrade Required` when the client asks for an unsupported protocol
+/// version, so handlers can propagate it with `?`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HandshakeError {
+    reason: &'static str,
+    status: StatusCode,
+}
+
+impl HandshakeError {
+    fn bad_request(reason: &'static str) -> HandshakeError {
+        HandshakeError {
+            reason,
+            status: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// The status of the response to the invalid handshake.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid WebSocket handshake: {}", self.reason)
+    }
+}
+
+impl Error for HandshakeError {}
+
+impl From<HandshakeError> for IronError {
+    fn from(err: HandshakeError) -> IronError {
+        let status = err.status;
+        let mut err = IronError::new(err, status);
+        if status == StatusCode::UPGRADE_REQUIRED {
+            err.response.headers.insert(
+                headers::SEC_WEBSOCKET_VERSION,
+                headers::HeaderValue::from_static("13"),
+            );
+        }
+        err
+    }
+}
+
+/// A validated WebSocket handshake, ready to be accepted.
+pub struct WebSocketUpgrade {
+    key: String,
+    requested: Vec<String>,
+    protocol: Option<String>,
+    on_upgrade: OnUpgrade,
+}
+
+impl WebSocketUpgrade {
+    /// Validate the WebSocket handshake of a request.
+    ///
+    /// This takes the body of the request, which carries the connection
+    /// once it has been upgraded.
+    pub fn from_request(req: &mut Request) -> Result<WebSocketUpgrade, HandshakeError> {
+        let bad_request = HandshakeError::bad_request;
+
+        if req.method != Method::GET || req.version < HttpVersion::HTTP_11 {
+            return Err(bad_request("expected a HTTP/1.1 GET request"));
+        }
+        if !header_has_token(req, headers::CONNECTION, "upgrade") {
+            return Err(bad_request("missing `Connection: upgrade`"));
+        }
+        if !header_has_token(req, headers::UPGRADE, "websocket") {
+            return Err(bad_request("missing `Upgrade: websocket`"));
+        }
+        if req
+            .headers
+            .get(headers::SEC_WEBSOCKET_VERSION)
+            .map(|v| v.as_bytes())
+            != Some(b"13")
+        {
+            return Err(HandshakeError {
+                reason: "unsupported version",
+                status: StatusCode::UPGRADE_REQUIRED,
+            });
+        }
+
+        let key = match req
+            .headers
+            .get(headers::SEC_WEBSOCKET_KEY)
+            .and_then(|k| k.to_str().ok())
+        {
+            Some(key) if base64::decode(key).map(|k| k.len() == 16).unwrap_or(false) => {
+                key.to_owned()
+            }
+            _ => return Err(bad_request("missing or invalid `Sec-WebSocket-Key`")),
+        };
+
+        let requested = req
+            .headers
+            .get_all(headers::SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|protocol| protocol.trim().to_owned())
+            .filter(|protocol| !protocol.is_empty())
+            .collect();
+
+        let on_upgrade = match req.body.take() {
+            Some(body) => body.on_upgrade(),
+            None => return Err(bad_request("the request body was already taken")),
+        };
+
+        Ok(WebSocketUpgrade {
+            key,
+            requested,
+            protocol: None,
+            on_upgrade,
+        })
+    }
+
+    /// The subprotocols offered by the client, in order of preference.
+    pub fn protocols(&self) -> Vec<&str> {
+        self.requested.iter().map(|p| p.as_str()).collect()
+    }
+
+    /// Select the subprotocol to speak with the client.
+    ///
+    /// This is ignored unless `protocol` was offered by the client.
+    pub fn protocol<P: AsRef<str>>(mut self, protocol: P) -> WebSocketUpgrade {
+        if self.requested.iter().any(|p| p == protocol.as_ref()) {
+            self.protocol = Some(protocol.as_ref().to_owned());
+        }
+        self
+    }
+
+    /// Accept the handshake, running `callback` on a new thread once the
+    /// connection has been upgraded.
+    ///
+    /// The returned modifier sets the `101 Switching Protocols` status and
+    /// the handshake headers on the `Response`.
+    pub fn accept<F>(self, callback: F) -> Accept
+    where
+        F: FnOnce(WebSocket) + Send + 'static,
+    {
+        Accept {
+            upgrade: self,
+            callback: Box::new(callback),
+        }
+    }
+}
+
+fn header_has_token(req: &Request, name: headers::HeaderName, token: &str) -> bool {
+    req.headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(ACCEPT_GUID.as_bytes());
+    base64::encode(&sha1.digest().bytes())
+}
+
+/// A modifier accepting a WebSocket handshake, created by
+/// `WebSocketUpgrade::accept`.
+pub struct Accept {
+    upgrade: WebSocketUpgrade,
+    callback: Box<dyn FnOnce(WebSocket) + Send>,
+}
+
+impl Modifier<Response> for Accept {
+    fn modify(self, res: &mut Response) {
+        let Accept { upgrade, callback } = self;
+
+        res.status = Some(StatusCode::SWITCHING_PROTOCOLS);
+        res.body = None;
+        res.headers.insert(
+            headers::CONNECTION,
+            headers::HeaderValue::from_static("upgrade"),
+        );
+        res.headers.insert(
+            headers::UPGRADE,
+            headers::HeaderValue::from_static("websocket"),
+        );
+        // The accept key is base64, so it is always a valid header value.
+        res.headers.insert(
+            headers::SEC_WEBSOCKET_ACCEPT,
+            accept_key(&upgrade.key).parse().unwrap(),
+        );
+        if let Some(ref protocol) = upgrade.protocol {
+            // The protocol was offered in a header, so it is a valid header value.
+            res.headers
+                .insert(headers::SEC_WEBSOCKET_PROTOCOL, protocol.parse().unwrap());
+        }
+
+        res.extensions.insert::<PendingUpgrade>(PendingUpgrade {
+            on_upgrade: upgrade.on_upgrade,
+            protocol: upgrade.protocol,
+            callback,
+        });
+    }
+}
+
+/// An accepted upgrade, waiting for the response to be sent.
+///
+/// `IronHandler` takes this from the `Response` and drives it on the
+/// server's event loop.
+pub(crate) struct PendingUpgrade {
+    on_upgrade: OnUpgrade,
+    protocol: Option<String>,
+    callback: Box<dyn FnOnce(WebSocket) + Send>,
+}
+
+impl Key for PendingUpgrade {
+    type Value = PendingUpgrade;
+}
+
+impl PendingUpgrade {
+    /// Take the pending upgrade out of a `Response`, if it accepted one.
+    pub(crate) fn take(res: &mut Response) -> Option<PendingUpgrade> {
+        res.extensions.remove::<PendingUpgrade>()
+    }
+
+    /// Wait for the connection to be upgraded, then hand it to the callback.
+    ///
+    /// This must be run on the server's event loop, since the upgraded
+    /// connection is driven from there.
+    pub(crate) fn run(self) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        let PendingUpgrade {
+            on_upgrade,
+            protocol,
+            callback,
+        } = self;
+
+        Box::new(
+            on_upgrade
+                .map_err(|e| error!("Error upgrading connection: {}", e))
+                .map(move |upgraded| {
+                    let socket = WebSocket::new(bridge(upgraded), protocol);
+                    thread::spawn(move || callback(socket));
+                }),
+        )
+    }
+}
+
+// Connects the upgraded connection to a pair of channels, so it can be used
+// with blocking reads and writes from another thread.
+fn bridge(upgraded: Upgraded) -> (ChannelReader, UnboundedSender<Vec<u8>>) {
+    let (reader, writer) = upgraded.split();
+    let (incoming_tx, incoming_rx) = std_mpsc::channel();
+    let (outgoing_tx, outgoing_rx) = mpsc::unbounded::<Vec<u8>>();
+
+    let read = future::loop_fn(reader, move |reader| {
+        let incoming_tx = incoming_tx.clone();
+        async_io::read(reader, vec![0; 4096]).and_then(move |(reader, mut buf, len)| {
+            buf.truncate(len);
+            // Stop reading at the end of the stream or once the socket was dropped.
+            if len == 0 || incoming_tx.send(buf).is_err() {
+                Ok(future::Loop::Break(()))
+            } else {
+                Ok(future::Loop::Continue(reader))
+            }
+        })
+    });
+
+    let write = outgoing_rx
+        .map_err(|()| io::Error::new(io::ErrorKind::Other, "websocket channel failed"))
+        .fold(writer, |writer, frame| {
+            async_io::write_all(writer, frame).map(|(writer, _)| writer)
+        })
+        .and_then(async_io::shutdown);
+
+    hyper::rt::spawn(read.map_err(|e| debug!("WebSocket read ended: {}", e)));
+    hyper::rt::spawn(
+        write
+            .map(|_| ())
+            .map_err(|e| debug!("WebSocket write ended: {}", e)),
+    );
+
+    (
+        ChannelReader {
+            receiver: incoming_rx,
+            buf: Vec::new(),
+            pos: 0,
+        },
+        outgoing_tx,
+    )
+}
+
+// A blocking reader over the chunks received from the connection.
+struct ChannelReader {
+    receiver: std_mpsc::Receiver<Vec<u8>>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.buf = chunk;
+                    self.pos = 0;
+                }
+                // The connection was closed.
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// A WebSocket message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// A UTF-8 text message.
+    Text(String),
+    /// A binary message.
+    Binary(Vec<u8>),
+    /// A ping. Pings from the client are answered automatically.
+    Ping(Vec<u8>),
+    /// A pong, answering a ping.
+    Pong(Vec<u8>),
+    /// A request to close the connection, with an optional status code
+    /// and reason.
+    Close(Option<(u16, String)>),
+}
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// A message-oriented WebSocket connection to a client.
+///
+/// All methods block, so a `WebSocket` should be used from its own thread,
+/// like the one `WebSocketUpgrade::accept` runs its callback on.
+pub struct WebSocket {
+    reader: Box<dyn Read + Send>,
+    writer: UnboundedSender<Vec<u8>>,
+    protocol: Option<String>,
+    close_sent: bool,
+    close_received: bool,
+    // The opcode and data of a fragmented message being received, kept
+    // when `recv` returns a pong received between its fragments.
+    fragments: Option<(u8, Vec<u8>)>,
+}
+
+impl WebSocket {
+    fn new<R>(
+        (reader, writer): (R, UnboundedSender<Vec<u8>>),
+        protocol: Option<String>,
+    ) -> WebSocket
+    where
+        R: Read + Send + 'static,
+    {
+        WebSocket {
+            reader: Box::new(reader),
+            writer,
+            protocol,
+            close_sent: false,
+            close_received: false,
+            fragments: None,
+        }
+    }
+
+    /// The subprotocol selected during the handshake.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Receive the next message from the client.
+    ///
+    /// Pings are answered automatically and closing handshakes started by
+    /// the client are completed before `Message::Close` is returned. Reading
+    /// after that fails with `ErrorKind::NotConnected`.
+    pub fn recv(&mut self) -> io::Result<Message> {
+        if self.close_received {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the websocket was closed",
+            ));
+        }
+
+        loop {
+            let (fin, opcode, payload) = read_frame(&mut self.reader)?;
+
+            match opcode {
+                OP_PING => {
+                    if !self.close_sent {
+                        self.write_frame(OP_PONG, &payload)?;
+                    }
+                }
+                OP_PONG => return Ok(Message::Pong(payload)),
+                OP_CLOSE => {
+                    self.close_received = true;
+                    if !self.close_sent {
+                        self.write_frame(OP_CLOSE, &payload[..payload.len().min(2)])?;
+                        self.close_sent = true;
+                    }
+                    return Ok(Message::Close(parse_close(&payload)));
+                }
+                OP_TEXT | OP_BINARY if self.fragments.is_none() => {
+                    self.fragments = Some((opcode, payload))
+                }
+                OP_CONTINUATION if self.fragments.is_some() => {
+                    let data = &mut self.fragments.as_mut().unwrap().1;
+                    if data.len() as u64 + payload.len() as u64 > MAX_MESSAGE_SIZE {
+                        return Err(protocol_error("message too large"));
+                    }
+                    data.extend_from_slice(&payload);
+                }
+                _ => return Err(protocol_error("unexpected opcode")),
+            }
+
+            // Control frames may arrive between fragments, so only a final
+            // data frame completes the message.
+            if fin && opcode < OP_CLOSE {
+                return match self.fragments.take() {
+                    Some((OP_TEXT, data)) => String::from_utf8(data)
+                        .map(Message::Text)
+                        .map_err(|_| protocol_error("invalid UTF-8 in text message")),
+                    Some((_, data)) => Ok(Message::Binary(data)),
+                    None => Err(protocol_error("unexpected continuation")),
+                };
+            }
+        }
+    }
+
+    /// Send a message to the client.
+    ///
+    /// Sending `Message::Close` starts the closing handshake; no other
+    /// messages can be sent after it.
+    pub fn send(&mut self, message: Message) -> io::Result<()> {
+        if self.close_sent {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the websocket was closed",
+            ));
+        }
+
+        match message {
+            Message::Text(text) => self.write_frame(OP_TEXT, text.as_bytes()),
+            Message::Binary(data) => self.write_frame(OP_BINARY, &data),
+            Message::Ping(data) => self.write_frame(OP_PING, &data),
+            Message::Pong(data) => self.write_frame(OP_PONG, &data),
+            Message::Close(reason) => {
+                let mut payload = vec![];
+                if let Some((code, reason)) = reason {
+                    payload.extend_from_slice(&[(code >> 8) as u8, code as u8]);
+                    payload.extend_from_slice(reason.as_bytes());
+                }
+                self.close_sent = true;
+                self.write_frame(OP_CLOSE, &payload)
+            }
+        }
+    }
+
+    /// Start the closing handshake with a normal closure status.
+    pub fn close(&mut self) -> io::Result<()> {
+        self.send(Message::Close(Some((1000, String::new()))))
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        if payload.len() < 126 {
+            frame.push(payload.len() as u8);
+        } else if payload.len() <= 0xFFFF {
+            frame.push(126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        } else {
+            frame.push(127);
+            frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        }
+        frame.extend_from_slice(payload);
+
+        self.writer
+            .unbounded_send(frame)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the connection was closed"))
+    }
+}
+
+// Reads a single frame sent by a client, which are always masked.
+fn read_frame<R: Read + ?Sized>(reader: &mut R) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head)?;
+
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0F;
+    if head[0] & 0x70 != 0 {
+        return Err(protocol_error("reserved bits set"));
+    }
+    if head[1] & 0x80 == 0 {
+        return Err(protocol_error("client frames must be masked"));
+    }
+
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    if len > MAX_MESSAGE_SIZE {
+        return Err(protocol_error("frame too large"));
+    }
+    if opcode >= OP_CLOSE && (!fin || len > 125) {
+        return Err(protocol_error("invalid control frame"));
+    }
+
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask)?;
+
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok((fin, opcode, payload))
+}
+
+fn parse_close(payload: &[u8]) -> Option<(u16, String)> {
+    if payload.len() < 2 {
+        return None;
+    }
+    let code = (u16::from(payload[0]) << 8) | u16::from(payload[1]);
+    let reason = String::from_utf8_lossy(&payload[2..]).into_owned();
+    Some((code, reason))
+}
+
+fn protocol_error(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use futures::sync::mpsc::UnboundedReceiver;
+
+    fn client_frame(opcode: u8, fin: bool, payload: &[u8]) -> Vec<u8> {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![
+            if fin { 0x80 } else { 0 } | opcode,
+            0x80 | payload.len() as u8,
+        ];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    fn socket(input: Vec<u8>) -> (WebSocket, UnboundedReceiver<Vec<u8>>) {
+        let (tx, rx) = mpsc::unbounded();
+        (WebSocket::new((Cursor::new(input), tx), None), rx)
+    }
+
+    fn upgrade_request() -> Request {
+        let mut req = Request::stub();
+        req.headers
+            .insert(headers::CONNECTION, "keep-alive, Upgrade".parse().unwrap());
+        req.headers
+            .insert(headers::UPGRADE, "websocket".parse().unwrap());
+        req.headers
+            .insert(headers::SEC_WEBSOCKET_VERSION, "13".parse().unwrap());
+        req.headers.insert(
+            headers::SEC_WEBSOCKET_KEY,
+            "dGhlIHNhbXBsZSBub25jZQ==".parse().unwrap(),
+        );
+        req.headers.insert(
+            headers::SEC_WEBSOCKET_PROTOCOL,
+            "chat, superchat".parse().unwrap(),
+        );
+        req
+    }
+
+    #[test]
+    fn test_accept_key() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_handshake() {
+        let upgrade = WebSocketUpgrade::from_request(&mut upgrade_request()).unwrap();
+        assert_eq!(upgrade.protocols(), vec!["chat", "superchat"]);
+
+        let upgrade = upgrade.protocol("superchat").protocol("unoffered");
+        let mut res = Response::with(upgrade.accept(|_| {}));
+
+        assert_eq!(res.status, Some(StatusCode::SWITCHING_PROTOCOLS));
+        assert_eq!(
+            res.headers.get(headers::SEC_WEBSOCKET_ACCEPT).unwrap(),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(
+            res.headers.get(headers::SEC_WEBSOCKET_PROTOCOL).unwrap(),
+            "superchat"
+        );
+        assert!(PendingUpgrade::take(&mut res).is_some());
+    }
+
+    #[test]
+    fn test_invalid_handshake() {
+        let mut req = upgrade_request();
+        req.headers.remove(headers::UPGRADE);
+        let err = WebSocketUpgrade::from_request(&mut req).err().unwrap();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        let mut req = upgrade_request();
+        req.headers
+            .insert(headers::SEC_WEBSOCKET_VERSION, "8".parse().unwrap());
+        let err = WebSocketUpgrade::from_request(&mut req).err().unwrap();
+        assert_eq!(err.status(), StatusCode::UPGRADE_REQUIRED);
+
+        let err = IronError::from(err);
+        assert_eq!(err.response.status, Some(StatusCode::UPGRADE_REQUIRED));
+        assert_eq!(
+            err.response
+                .headers
+                .get(headers::SEC_WEBSOCKET_VERSION)
+                .unwrap(),
+            "13"
+        );
+    }
+
+    #[test]
+    fn test_fragmented_message() {
+        let mut input = client_frame(OP_TEXT, false, b"hel");
+        input.extend(client_frame(OP_PING, true, b"p"));
+        input.extend(client_frame(OP_CONTINUATION, true, b"lo"));
+        let (mut socket, rx) = socket(input);
+
+        assert_eq!(socket.recv().unwrap(), Message::Text("hello".to_owned()));
+
+        drop(socket);
+        let sent: Vec<_> = rx.wait().map(|frame| frame.unwrap()).collect();
+        assert_eq!(sent, vec![vec![0x80 | OP_PONG, 1, b'p']]);
+    }
+
+    #[test]
+    fn test_pong_between_fragments() {
+        let mut input = client_frame(OP_BINARY, false, b"ab");
+        input.extend(client_frame(OP_PONG, true, b"p"));
+        input.extend(client_frame(OP_CONTINUATION, false, b"c"));
+        input.extend(client_frame(OP_CONTINUATION, true, b"d"));
+        let (mut socket, _rx) = socket(input);
+
+        assert_eq!(socket.recv().unwrap(), Message::Pong(b"p".to_vec()));
+        assert_eq!(socket.recv().unwrap(), Message::Binary(b"abcd".to_vec()));
+    }
+
+    #[test]
+    fn test_close_handshake() {
+        let (mut socket, rx) = socket(client_frame(
+            OP_CLOSE,
+            true,
+            &[0x03, 0xE8, b'b', b'y', b'e'],
+        ));
+
+        assert_eq!(
+            socket.recv().unwrap(),
+            Message::Close(Some((1000, "bye".to_owned())))
+        );
+        assert!(socket.recv().is_err());
+        assert!(socket.send(Message::Text("late".to_owned())).is_err());
+
+        drop(socket);
+        let sent: Vec<_> = rx.wait().map(|frame| frame.unwrap()).collect();
+        assert_eq!(sent, vec![vec![0x80 | OP_CLOSE, 2, 0x03, 0xE8]]);
+    }
+
+    #[test]
+    fn test_unmasked_frame() {
+        let (mut socket, _rx) = socket(vec![0x80 | OP_TEXT, 0]);
+        assert_eq!(
+            socket.recv().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -7,6 +7,9 @@
 extern crate iron;
 extern crate sequence_trie;
 
+#[cfg(test)]
+extern crate router;
+
 pub use mount::{Mount, OriginalUrl, NoMatch};
 
 mod mount;
@@ -2,14 +2,15 @@ use std::error::Error;
 use std::path::{Path, Component};
 use iron::prelude::*;
 use iron::middleware::Handler;
-use iron::{StatusCode, Url, typemap};
+use iron::StatusCode;
 use sequence_trie::SequenceTrie;
 use std::fmt;
 
 /// Exposes the original, unmodified path to be stored in `Request::extensions`.
-#[derive(Copy, Clone)]
-pub struct OriginalUrl;
-impl typemap::Key for OriginalUrl { type Value = Url; }
+///
+/// The key is defined by Iron, so that other middleware like `router` can
+/// read it without depending on `mount`.
+pub use iron::request::OriginalUrl;
 
 /// `Mount` is a simple mounting middleware.
 ///
@@ -136,3 +137,45 @@ impl Handler for Mount {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use iron::prelude::*;
+    use iron::testing;
+    use iron::StatusCode;
+    use router::{try_url_for, Router};
+
+    use super::Mount;
+
+    fn mount() -> Mount {
+        let mut router = Router::new();
+        router.get("/", |req: &mut Request| {
+            let params: HashMap<_, _> = vec![("id".to_owned(), "7".to_owned())].into_iter().collect();
+            let url = try_url_for(req, "user", params).unwrap();
+            Ok(Response::with((StatusCode::OK, url.to_string())))
+        }, "index");
+        router.get("/users/:id/", |_: &mut Request| Ok(Response::with((StatusCode::OK, "user"))), "user");
+
+        let mut mount = Mount::new();
+        mount.mount("/api/", router);
+        mount
+    }
+
+    #[test]
+    fn test_router_redirect() {
+        let res = testing::get("/api/users/7?tab=files").handle(&mount());
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.header("location"), Some("http://localhost/api/users/7/?tab=files"));
+
+        assert_eq!(testing::get("/api/users/7/").handle(&mount()).text(), "user");
+    }
+
+    #[test]
+    fn test_router_url_for() {
+        let mount = mount();
+        assert_eq!(testing::get("/api").handle(&mount).text(), "http://localhost/api/users/7/");
+        assert_eq!(testing::get("/api/").handle(&mount).text(), "http://localhost/api/users/7/");
+    }
+}
@@ -0,0 +1,214 @@
+use std::any::type_name;
+use std::sync::Arc;
+
+use iron::{AfterMiddleware, BeforeMiddleware, Chain, Handler};
+use iron::{method, Method};
+
+/// A group of routes sharing a path prefix and middleware, created by
+/// `Router::group`.
+///
+/// The middleware linked to a group wraps every route of the group, whether
+/// it was added before or after linking it. Groups can be nested, in which
+/// case the middleware of the outer group runs first.
+pub struct RouteGroup {
+    prefix: String,
+    befores: Vec<(String, Arc<Box<dyn BeforeMiddleware>>)>,
+    afters: Vec<(String, Arc<Box<dyn AfterMiddleware>>)>,
+    routes: Vec<GroupRoute>
+}
+
+// A route of a group, with its glob relative to the group's parent.
+pub(crate) struct GroupRoute {
+    // `None` for routes matching any method.
+    pub method: Option<method::Method>,
+    pub glob: String,
+    pub handler: Box<dyn Handler>,
+    pub route_id: String
+}
+
+impl RouteGroup {
+    pub(crate) fn new(prefix: &str) -> RouteGroup {
+        RouteGroup {
+            prefix: prefix.to_owned(),
+            befores: vec![],
+            afters: vec![],
+            routes: vec![]
+        }
+    }
+
+    /// Link a `BeforeMiddleware` to every route of the group.
+    pub fn link_before<B: BeforeMiddleware>(&mut self, before: B) -> &mut RouteGroup {
+        let before = Box::new(before) as Box<dyn BeforeMiddleware>;
+        self.befores.push((type_name::<B>().to_owned(), Arc::new(before)));
+        self
+    }
+
+    /// Link an `AfterMiddleware` to every route of the group.
+    pub fn link_after<A: AfterMiddleware>(&mut self, after: A) -> &mut RouteGroup {
+        let after = Box::new(after) as Box<dyn AfterMiddleware>;
+        self.afters.push((type_name::<A>().to_owned(), Arc::new(after)));
+        self
+    }
+
+    /// Add a route to the group, like `Router::route`.
+    ///
+    /// `glob` is relative to the prefix of the group, while `route_id` is
+    /// global to the `Router`, so `url_for` generates the full path.
+    pub fn route<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, method: method::Method, glob: S, handler: H, route_id: I) -> &mut RouteGroup {
+        self.add(Some(method), glob.as_ref(), Box::new(handler), route_id.as_ref())
+    }
+
+    /// Like route, but specialized to the `Get` method.
+    pub fn get<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, glob: S, handler: H, route_id: I) -> &mut RouteGroup {
+        self.route(Method::GET, glob, handler, route_id)
+    }
+
+    /// Like route, but specialized to the `Post` method.
+    pub fn post<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, glob: S, handler: H, route_id: I) -> &mut RouteGroup {
+        self.route(Method::POST, glob, handler, route_id)
+    }
+
+    /// Like route, but specialized to the `Put` method.
+    pub fn put<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, glob: S, handler: H, route_id: I) -> &mut RouteGroup {
+        self.route(Method::PUT, glob, handler, route_id)
+    }
+
+    /// Like route, but specialized to the `Delete` method.
+    pub fn delete<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, glob: S, handler: H, route_id: I) -> &mut RouteGroup {
+        self.route(Method::DELETE, glob, handler, route_id)
+    }
+
+    /// Like route, but specialized to the `Head` method.
+    pub fn head<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, glob: S, handler: H, route_id: I) -> &mut RouteGroup {
+        self.route(Method::HEAD, glob, handler, route_id)
+    }
+
+    /// Like route, but specialized to the `Patch` method.
+    pub fn patch<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, glob: S, handler: H, route_id: I) -> &mut RouteGroup {
+        self.route(Method::PATCH, glob, handler, route_id)
+    }
+
+    /// Like route, but specialized to the `Options` method.
+    pub fn options<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, glob: S, handler: H, route_id: I) -> &mut RouteGroup {
+        self.route(Method::OPTIONS, glob, handler, route_id)
+    }
+
+    /// Like `Router::any`, matching any method.
+    pub fn any<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, glob: S, handler: H, route_id: I) -> &mut RouteGroup {
+        self.add(None, glob.as_ref(), Box::new(handler), route_id.as_ref())
+    }
+
+    /// Add a nested group, whose prefix is relative to this group's.
+    pub fn group<S: AsRef<str>, F: FnOnce(&mut RouteGroup)>(&mut self, prefix: S, f: F) -> &mut RouteGroup {
+        let mut group = RouteGroup::new(prefix.as_ref());
+        f(&mut group);
+        self.routes.extend(group.finish());
+        self
+    }
+
+    fn add(&mut self, method: Option<method::Method>, glob: &str, handler: Box<dyn Handler>, route_id: &str) -> &mut RouteGroup {
+        self.routes.push(GroupRoute {
+            method,
+            glob: glob.to_owned(),
+            handler,
+            route_id: route_id.to_owned()
+        });
+        self
+    }
+
+    // Prefix the routes of the group and wrap them in its middleware.
+    pub(crate) fn finish(self) -> Vec<GroupRoute> {
+        let RouteGroup { prefix, befores, afters, routes } = self;
+        routes.into_iter().map(|route| {
+            let handler = if befores.is_empty() && afters.is_empty() {
+                route.handler
+            } else {
+                let mut chain = Chain::new(route.handler);
+                for (name, before) in &befores {
+                    chain.link_before_named(name.clone(), before.clone());
+                }
+                for (name, after) in &afters {
+                    chain.link_after_named(name.clone(), after.clone());
+                }
+                Box::new(chain) as Box<dyn Handler>
+            };
+
+            GroupRoute {
+                method: route.method,
+                glob: join(&prefix, &route.glob),
+                handler,
+                route_id: route.route_id
+            }
+        }).collect()
+    }
+}
+
+// Join a group prefix and a glob, with exactly one slash between them.
+fn join(prefix: &str, glob: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    if glob.is_empty() {
+        if prefix.is_empty() { "/".to_owned() } else { prefix.to_owned() }
+    } else if glob.starts_with('/') {
+        format!("{}{}", prefix, glob)
+    } else {
+        format!("{}/{}", prefix, glob)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::join;
+
+    use iron::prelude::*;
+    use iron::testing;
+    use iron::{headers, StatusCode};
+
+    use {url_for, Router};
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join("/admin", "/users"), "/admin/users");
+        assert_eq!(join("/admin/", "users"), "/admin/users");
+        assert_eq!(join("/admin", "/"), "/admin/");
+        assert_eq!(join("/admin", ""), "/admin");
+        assert_eq!(join("", ""), "/");
+    }
+
+    #[test]
+    fn test_group() {
+        let mut router = Router::new();
+        router.get("/", |_: &mut Request| Ok(Response::with((StatusCode::OK, "home"))), "home");
+        router.group("/admin", |g| {
+            g.get("/users/:id", |req: &mut Request| {
+                let url = url_for(req, "admin_user", vec![("id".to_owned(), "2".to_owned())].into_iter().collect());
+                Ok(Response::with((StatusCode::OK, url.to_string())))
+            }, "admin_user");
+            g.link_before(|req: &mut Request| {
+                match req.headers.get(headers::AUTHORIZATION) {
+                    Some(_) => Ok(()),
+                    None => Err(IronError::new(::NoRoute, StatusCode::UNAUTHORIZED))
+                }
+            });
+            g.group("/v1", |g| {
+                g.link_after(|_: &mut Request, mut res: Response| {
+                    res.headers.insert("x-version", "1".parse().unwrap());
+                    Ok(res)
+                });
+                g.any("/", |_: &mut Request| Ok(Response::with((StatusCode::OK, "v1"))), "admin_v1");
+            });
+        });
+
+        let res = testing::get("/").handle(&router);
+        assert_eq!(res.text(), "home");
+
+        let res = testing::get("/admin/users/1").handle(&router);
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = testing::get("/admin/users/1").header("authorization", "yes").handle(&router);
+        assert_eq!(res.text(), "http://localhost/admin/users/2");
+
+        let res = testing::delete("/admin/v1/").header("authorization", "yes").handle(&router);
+        assert_eq!(res.text(), "v1");
+        assert_eq!(res.header("x-version"), Some("1"));
+    }
+}
@@ -4,13 +4,24 @@
 //! `Router` provides fast and flexible routing for Iron.
 
 extern crate iron;
+extern crate regex;
 extern crate route_recognizer as recognizer;
+#[cfg(feature = "serde_json")]
+#[macro_use]
+extern crate serde_json;
 extern crate url;
 
-pub use router::{Router, NoRoute, TrailingSlash};
+pub use router::{Router, NoRoute, MethodNotAllowed, TrailingSlash, TrailingSlashPolicy};
+pub use group::RouteGroup;
+pub use params::{ParseParam, ParamError};
+pub use table::{NotRouted, RouteInfo, RouteTable};
 pub use recognizer::Params;
-pub use url_for::url_for;
+pub use url_for::{url_for, try_url_for, try_path_for, UrlForError};
 
 mod router;
+mod group;
+mod params;
+mod routes;
+mod table;
 mod macros;
 mod url_for;
@@ -0,0 +1,107 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use iron::{IronError, IronResult, StatusCode};
+
+use recognizer::Params;
+
+/// Typed access to the `Params` of a route.
+///
+/// ```
+/// # extern crate iron;
+/// # extern crate router;
+/// # use iron::prelude::*;
+/// # use iron::StatusCode;
+/// use router::{ParseParam, Router};
+///
+/// fn show_user(req: &mut Request) -> IronResult<Response> {
+///     let id = req.extensions.get::<Router>().unwrap().parse::<u64>("id")?;
+///     Ok(Response::with((StatusCode::OK, format!("user {}", id))))
+/// }
+/// # fn main() {}
+/// ```
+pub trait ParseParam {
+    /// Parse the parameter `name`.
+    ///
+    /// A value which can't be parsed is the client's fault, and fails with a
+    /// `400 Bad Request`. A missing parameter is a mistake in the route, and
+    /// fails with a `500 Internal Server Error`.
+    fn parse<T>(&self, name: &str) -> IronResult<T>
+        where T: FromStr, T::Err: fmt::Display;
+}
+
+impl ParseParam for Params {
+    fn parse<T>(&self, name: &str) -> IronResult<T>
+        where T: FromStr, T::Err: fmt::Display
+    {
+        let value = match self.find(name) {
+            Some(value) => value,
+            None => return Err(IronError::new(ParamError::Missing(name.to_owned()),
+                                              StatusCode::INTERNAL_SERVER_ERROR))
+        };
+
+        value.parse().map_err(|e: T::Err| {
+            let err = ParamError::Invalid {
+                name: name.to_owned(),
+                value: value.to_owned(),
+                reason: e.to_string()
+            };
+            IronError::new(err, StatusCode::BAD_REQUEST)
+        })
+    }
+}
+
+/// The error returned when a route parameter can't be parsed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParamError {
+    /// The route has no parameter of this name.
+    Missing(String),
+    /// The value of the parameter has the wrong type.
+    Invalid {
+        /// The name of the parameter.
+        name: String,
+        /// The value of the parameter.
+        value: String,
+        /// Why the value couldn't be parsed.
+        reason: String
+    }
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParamError::Missing(ref name) => write!(f, "No route parameter `{}`.", name),
+            ParamError::Invalid { ref name, ref value, ref reason } =>
+                write!(f, "Invalid route parameter `{}` = {:?}: {}", name, value, reason)
+        }
+    }
+}
+
+impl Error for ParamError {}
+
+#[cfg(test)]
+mod test {
+    use super::{ParamError, ParseParam};
+
+    use iron::StatusCode;
+    use recognizer::Params;
+
+    #[test]
+    fn test_parse() {
+        let mut params = Params::new();
+        params.insert("id".to_owned(), "42".to_owned());
+        params.insert("slug".to_owned(), "iron".to_owned());
+
+        assert_eq!(params.parse::<u64>("id").unwrap(), 42);
+        assert_eq!(params.parse::<String>("slug").unwrap(), "iron");
+
+        let err = params.parse::<u64>("slug").unwrap_err();
+        assert_eq!(err.response.status, Some(StatusCode::BAD_REQUEST));
+        assert_eq!(err.to_string(), "Invalid route parameter `slug` = \"iron\": invalid digit found in string");
+        assert!(err.error.is::<ParamError>());
+
+        let err = params.parse::<u64>("name").unwrap_err();
+        assert_eq!(err.response.status, Some(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+}
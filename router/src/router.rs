@@ -7,20 +7,50 @@ use iron::{Request, Response, Handler, IronResult, IronError};
 use iron::{StatusCode, method, Method, headers};
 use iron::typemap::Key;
 use iron::modifiers::Redirect;
+use iron::request::OriginalUrl;
 
-use recognizer::Router as Recognizer;
+use group::RouteGroup;
 use recognizer::{Match, Params};
+use routes::Routes;
+use table::RouteInfo;
 
 
 pub struct RouterInner {
     // The routers, specialized by method.
-    pub routers: HashMap<method::Method, Recognizer<Box<dyn Handler>>>,
+    pub routers: HashMap<method::Method, Routes>,
     // Routes that accept any method.
-    pub wildcard: Recognizer<Box<dyn Handler>>,
+    pub wildcard: Routes,
     // Used in URL generation.
-    pub route_ids: HashMap<String, String>
+    pub route_ids: HashMap<String, String>,
+    // Every route, in the order they were added.
+    pub routes: Vec<RouteInfo>,
+    // What to do with requests which only match with(out) a trailing slash.
+    pub trailing_slash: TrailingSlashPolicy
 }
 
+/// How a `Router` handles a request without a route, which would match a
+/// route if a trailing slash was added or removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingSlashPolicy {
+    /// Redirect with `301 Moved Permanently`. Clients may change the method
+    /// of the redirected request to `GET`. This is the default.
+    MovedPermanently,
+    /// Redirect with `308 Permanent Redirect`, which requires clients to
+    /// repeat the request with the same method and body.
+    PermanentRedirect,
+    /// Handle the request with the matching route, without redirecting.
+    Match,
+    /// Treat the request like any other request without a route.
+    Strict
+}
+
+impl Default for TrailingSlashPolicy {
+    fn default() -> TrailingSlashPolicy {
+        TrailingSlashPolicy::MovedPermanently
+    }
+}
+
+
 /// `Router` provides an interface for creating complex routes as middleware
 /// for the Iron framework.
 pub struct Router {
@@ -38,12 +68,31 @@ impl Router {
         Router {
             inner: Arc::new(RouterInner {
                 routers: HashMap::new(),
-                wildcard: Recognizer::new(),
-                route_ids: HashMap::new()
+                wildcard: Routes::new(),
+                route_ids: HashMap::new(),
+                routes: Vec::new(),
+                trailing_slash: TrailingSlashPolicy::default()
             })
         }
     }
 
+    /// Set how requests which only match a route with(out) a trailing slash
+    /// are handled.
+    ///
+    /// Redirects keep the query string of the request, and go to the
+    /// `OriginalUrl` of requests under a `mount::Mount`, so that mounted
+    /// routers redirect to the path requested by the client.
+    ///
+    /// ```
+    /// # use router::{Router, TrailingSlashPolicy};
+    /// let mut router = Router::new();
+    /// router.trailing_slash(TrailingSlashPolicy::PermanentRedirect);
+    /// ```
+    pub fn trailing_slash(&mut self, policy: TrailingSlashPolicy) -> &mut Router {
+        self.mut_inner().trailing_slash = policy;
+        self
+    }
+
     fn mut_inner(&mut self) -> &mut RouterInner {
         Arc::get_mut(&mut self.inner).expect("Cannot modify router at this point.")
     }
@@ -54,6 +103,27 @@ impl Router {
     /// `:param` for matching storing that segment of the request url in the `Params`
     /// object, which is stored in the request `extensions`.
     ///
+    /// Parameters can be constrained to only match some segments, with
+    /// `:id<int>` for integers, `:id<uuid>` for UUIDs or `:slug<[a-z-]+>` for
+    /// segments matching a regular expression. Routes which only differ by
+    /// their constraints are tried in the order they were added:
+    ///
+    /// ```ignore
+    /// router.get("/users/:id<int>", show_user, "user");
+    /// router.get("/users/:name", find_user, "user_by_name");
+    /// ```
+    ///
+    /// When none of them match, less specific routes are tried, such as
+    /// `/users/*path`, by matching the path against each of them in turn.
+    /// Adding the same method and glob again replaces the handler of the
+    /// earlier route.
+    ///
+    /// Constraints only apply to a single segment, so they can't contain
+    /// `/`. `route` panics on invalid constraints, such as invalid regular
+    /// expressions, like it does on a duplicate `route_id`.
+    ///
+    /// Use `ParseParam` to get parameters as other types than strings.
+    ///
     /// For instance, to route `Get` requests on any route matching
     /// `/users/:userid/:friend` and store `userid` and `friend` in
     /// the exposed Params object:
@@ -73,10 +143,11 @@ impl Router {
     /// authorized for this route before handling it.
     pub fn route<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, method: method::Method, glob: S, handler: H, route_id: I) -> &mut Router {
         self.mut_inner().routers
-            .entry(method)
-            .or_insert(Recognizer::new())
+            .entry(method.clone())
+            .or_insert_with(Routes::new)
             .add(glob.as_ref(), Box::new(handler));
         self.route_id(route_id.as_ref(), glob.as_ref());
+        self.mut_inner().routes.push(RouteInfo::new(Some(method), glob.as_ref(), route_id.as_ref()));
         self
     }
 
@@ -132,74 +203,207 @@ impl Router {
     pub fn any<S: AsRef<str>, H: Handler, I: AsRef<str>>(&mut self, glob: S, handler: H, route_id: I) -> &mut Router {
         self.mut_inner().wildcard.add(glob.as_ref(), Box::new(handler));
         self.route_id(route_id.as_ref(), glob.as_ref());
+        self.mut_inner().routes.push(RouteInfo::new(None, glob.as_ref(), route_id.as_ref()));
+        self
+    }
+
+    /// Every route of the `Router`, in the order they were added.
+    ///
+    /// Routes sharing a route id, for different methods, are listed
+    /// separately. The routes can also be served with `RouteTable`.
+    pub fn routes(&self) -> &[RouteInfo] {
+        &self.inner.routes
+    }
+
+    /// Describe the routes with the id `route_id`, for `routes`.
+    ///
+    /// Panics if there is no such route.
+    pub fn describe<I: AsRef<str>, D: Into<String>>(&mut self, route_id: I, description: D) -> &mut Router {
+        let description = description.into();
+        for route in self.routes_with_id(route_id.as_ref()) {
+            route.description = Some(description.clone());
+        }
+        self
+    }
+
+    /// Tag the routes with the id `route_id`, for `routes`.
+    ///
+    /// Panics if there is no such route.
+    pub fn tag<I: AsRef<str>, T: Into<String>>(&mut self, route_id: I, tag: T) -> &mut Router {
+        let tag = tag.into();
+        for route in self.routes_with_id(route_id.as_ref()) {
+            route.tags.push(tag.clone());
+        }
+        self
+    }
+
+    fn routes_with_id(&mut self, route_id: &str) -> impl Iterator<Item = &mut RouteInfo> {
+        if !self.inner.route_ids.contains_key(route_id) {
+            panic!("No route with that ID: {}", route_id);
+        }
+        let route_id = route_id.to_owned();
+        self.mut_inner().routes.iter_mut().filter(move |route| route.route_id == route_id)
+    }
+
+    /// Add a group of routes sharing a path prefix and middleware.
+    ///
+    /// ```
+    /// # extern crate iron;
+    /// # extern crate router;
+    /// # use iron::prelude::*;
+    /// # use iron::StatusCode;
+    /// # use router::Router;
+    /// # fn authenticate(_: &mut Request) -> IronResult<()> { Ok(()) }
+    /// # fn users(_: &mut Request) -> IronResult<Response> { Ok(Response::with(StatusCode::OK)) }
+    /// # fn main() {
+    /// let mut router = Router::new();
+    /// router.group("/admin", |g| {
+    ///     g.link_before(authenticate);
+    ///     g.get("/users", users, "admin_users");
+    /// });
+    /// # }
+    /// ```
+    ///
+    /// Routes are added to the router once `f` returns, with their globs
+    /// prefixed and their handlers wrapped in the group's middleware.
+    pub fn group<S: AsRef<str>, F: FnOnce(&mut RouteGroup)>(&mut self, prefix: S, f: F) -> &mut Router {
+        let mut group = RouteGroup::new(prefix.as_ref());
+        f(&mut group);
+        for route in group.finish() {
+            match route.method {
+                Some(method) => self.route(method, route.glob, route.handler, route.route_id),
+                None => self.any(route.glob, route.handler, route.route_id)
+            };
+        }
         self
     }
 
     fn recognize(&self, method: &method::Method, path: &str)
-                     -> Option<Match<&Box<dyn Handler>>> {
-        self.inner.routers.get(method).and_then(|router| router.recognize(path).ok())
-            .or(self.inner.wildcard.recognize(path).ok())
+                     -> Option<Match<&dyn Handler>> {
+        self.inner.routers.get(method).and_then(|router| router.recognize(path))
+            .or_else(|| self.inner.wildcard.recognize(path))
     }
 
-    fn handle_options(&self, path: &str) -> Response {
+    // The methods with a route matching `path`, including custom methods.
+    //
+    // A route registered with `any` allows all of the standard methods.
+    fn allowed_methods(&self, path: &str) -> Vec<method::Method> {
         static METHODS: &'static [method::Method] =
             &[Method::GET, Method::POST, Method::PUT,
               Method::DELETE, Method::HEAD, Method::PATCH];
 
-        // Get all the available methods and return them.
-        let mut options = vec![];
+        let mut allowed: Vec<method::Method> = self.inner.routers.iter()
+            .filter(|&(_, router)| router.recognize(path).is_some())
+            .map(|(method, _)| method.clone())
+            .collect();
 
-        for method in METHODS.iter() {
-            self.inner.routers.get(method).map(|router| {
-                if let Some(_) = router.recognize(path).ok() {
-                    options.push(method.clone());
+        if self.inner.wildcard.recognize(path).is_some() {
+            for method in METHODS {
+                if !allowed.contains(method) {
+                    allowed.push(method.clone());
                 }
-            });
+            }
         }
+
         // If GET is there, HEAD is also there.
-        if options.contains(&Method::GET) && !options.contains(&Method::HEAD) {
-            options.push(Method::HEAD);
+        if allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+            allowed.push(Method::HEAD);
         }
 
+        // Standard methods come first, in a fixed order, then custom ones by name.
+        allowed.sort_by(|a, b| {
+            let position = |m: &method::Method| METHODS.iter().position(|x| x == m).unwrap_or(METHODS.len());
+            position(a).cmp(&position(b)).then_with(|| a.as_str().cmp(b.as_str()))
+        });
+        allowed
+    }
+
+    fn handle_options(&self, path: &str) -> Response {
         let mut res = Response::with(StatusCode::OK);
-        for option in options {
+        for option in self.allowed_methods(path) {
             res.headers.append(headers::ALLOW, option.as_str().parse().unwrap());
         }
         res
     }
 
-    // Tests for a match by adding or removing a trailing slash.
-    fn redirect_slash(&self, req : &Request) -> Option<IronError> {
-        let mut url = req.url.clone();
-        let mut path = url.path().join("/");
-
-        if let Some(last_char) = path.chars().last() {
-            {
-                let mut path_segments = url.as_mut().path_segments_mut().unwrap();
-                if last_char == '/' {
-                    // We didn't recognize anything without a trailing slash; try again with one appended.
-                    path.pop();
-                    path_segments.pop();
-                } else {
-                    // We didn't recognize anything with a trailing slash; try again without it.
-                    path.push('/');
-                    path_segments.push("");
-                }
+    // The error for a request without a route, which is a 405 if the path
+    // has routes for other methods.
+    fn no_route(&self, path: &str) -> IronError {
+        let allowed = self.allowed_methods(path);
+        if allowed.is_empty() {
+            return IronError::new(NoRoute, StatusCode::NOT_FOUND);
+        }
+
+        let mut res = Response::with(StatusCode::METHOD_NOT_ALLOWED);
+        for method in &allowed {
+            res.headers.append(headers::ALLOW, method.as_str().parse().unwrap());
+        }
+        IronError {
+            error: Box::new(MethodNotAllowed(allowed)),
+            response: res
+        }
+    }
+
+    // The path with a trailing slash added or removed, if it has a route.
+    fn toggle_slash(&self, method: &method::Method, path: &str) -> Option<String> {
+        let mut path = path.to_owned();
+        match path.pop() {
+            // We didn't recognize anything with a trailing slash; try again without it.
+            Some('/') => (),
+            // We didn't recognize anything without a trailing slash; try again with one appended.
+            Some(last_char) => { path.push(last_char); path.push('/'); }
+            None => return None
+        }
+        self.recognize(method, &path).map(|_| path)
+    }
+
+    // Redirects to the URL with a trailing slash added or removed.
+    fn redirect_slash(&self, req: &Request, status: StatusCode) -> IronError {
+        // Under a mount, the path of the request is the end of the original
+        // path, so toggling the slash of the original URL gives the external
+        // path of the route.
+        let mut url = match req.extensions.get::<OriginalUrl>() {
+            Some(original) => original.clone(),
+            None => req.url.clone()
+        };
+
+        {
+            let mut path_segments = url.as_mut().path_segments_mut().unwrap();
+            if req.url.path().last() == Some(&"") {
+                path_segments.pop();
+            } else {
+                path_segments.push("");
             }
         }
 
-        self.recognize(&req.method, &path).and(
-            Some(IronError::new(TrailingSlash,
-                                (StatusCode::MOVED_PERMANENTLY, Redirect(url))))
-        )
+        IronError::new(TrailingSlash, (status, Redirect(url)))
+    }
+
+    // Give the request the params of the matched route, and the routes for
+    // `url_for`, returning the handler to dispatch it to.
+    fn dispatch<'a>(&self, req: &mut Request, matched: Match<&'a dyn Handler>) -> &'a dyn Handler {
+        req.extensions.insert::<Router>(matched.params);
+        req.extensions.insert::<RouterInner>(self.inner.clone());
+        matched.handler
     }
 
     fn handle_method(&self, req: &mut Request, path: &str) -> Option<IronResult<Response>> {
         if let Some(matched) = self.recognize(&req.method, path) {
-            req.extensions.insert::<Router>(matched.params);
-            req.extensions.insert::<RouterInner>(self.inner.clone());
-            Some(matched.handler.handle(req))
-        } else { self.redirect_slash(req).and_then(|redirect| Some(Err(redirect))) }
+            return Some(self.dispatch(req, matched).handle(req));
+        }
+
+        let status = match self.inner.trailing_slash {
+            TrailingSlashPolicy::MovedPermanently => StatusCode::MOVED_PERMANENTLY,
+            TrailingSlashPolicy::PermanentRedirect => StatusCode::PERMANENT_REDIRECT,
+            TrailingSlashPolicy::Match => {
+                let path = self.toggle_slash(&req.method, path)?;
+                let matched = self.recognize(&req.method, &path)?;
+                return Some(self.dispatch(req, matched).handle(req));
+            }
+            TrailingSlashPolicy::Strict => return None
+        };
+        self.toggle_slash(&req.method, path)?;
+        Some(Err(self.redirect_slash(req, status)))
     }
 }
 
@@ -217,11 +421,15 @@ impl Handler for Router {
                 // For HEAD, fall back to GET. Hyper ensures no response body is written.
                 Method::HEAD => {
                     req.method = Method::GET;
-                    self.handle_method(req, &path).unwrap_or(
-                        Err(IronError::new(NoRoute, StatusCode::NOT_FOUND))
-                    )
+                    match self.handle_method(req, &path) {
+                        Some(result) => result,
+                        None => {
+                            req.method = Method::HEAD;
+                            Err(self.no_route(&path))
+                        }
+                    }
                 }
-                _ => Err(IronError::new(NoRoute, StatusCode::NOT_FOUND))
+                _ => Err(self.no_route(&path))
             }
         )
     }
@@ -242,6 +450,23 @@ impl Error for NoRoute {
     fn description(&self) -> &str { "No Route" }
 }
 
+/// The error thrown by router if there are routes for the path of the
+/// request, but not for its method. It is always accompanied by a Method
+/// Not Allowed response, with the allowed methods in its `Allow` header.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MethodNotAllowed(pub Vec<method::Method>);
+
+impl fmt::Display for MethodNotAllowed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let allowed: Vec<&str> = self.0.iter().map(|m| m.as_str()).collect();
+        write!(f, "Method not allowed, expected one of: {}", allowed.join(", "))
+    }
+}
+
+impl Error for MethodNotAllowed {
+    fn description(&self) -> &str { "Method Not Allowed" }
+}
+
 /// The error thrown by router if a request was redirected
 /// by adding or removing a trailing slash.
 #[derive(Debug, PartialEq, Eq)]
@@ -260,7 +485,12 @@ impl Error for TrailingSlash {
 #[cfg(test)]
 mod test {
     use super::Router;
-    use iron::{headers, method, Method, StatusCode, Request, Response};
+    use iron::{headers, method, Handler, Method, StatusCode, Request, Response};
+
+    // A handler answering with `body`.
+    fn body(body: &'static str) -> impl Handler {
+        move |_: &mut Request| Ok(Response::with((StatusCode::OK, body)))
+    }
 
     #[test]
     fn test_handle_options_post() {
@@ -352,4 +582,155 @@ mod test {
         assert!(router.recognize(&Method::PUT, "/foo").is_none());
         assert!(router.recognize(&Method::PUT, "/upload/foo").is_some());
     }
+
+    #[test]
+    fn test_constrained_params() {
+        use iron::testing;
+        use ParseParam;
+
+        let mut router = Router::new();
+        router.get("/users/:id<int>", |req: &mut Request| {
+            let id = req.extensions.get::<Router>().unwrap().parse::<u8>("id")?;
+            Ok(Response::with((StatusCode::OK, format!("user {}", id))))
+        }, "user");
+        router.get("/users/:name", body("by name"), "user_by_name");
+
+        assert_eq!(testing::get("/users/7").handle(&router).text(), "user 7");
+        assert_eq!(testing::get("/users/iron").handle(&router).text(), "by name");
+        let res = testing::get("/users/300").handle(&router);
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_constrained_siblings() {
+        use iron::testing;
+
+        let mut router = Router::new();
+        router.get("/files/:id<int>", body("id"), "file");
+        router.get("/files/*path", body("path"), "file_path");
+        router.get("/pages/:id<int>", body("page"), "page");
+        router.get("/pages/about", body("about"), "about");
+        router.post("/pages/:slug", body("new page"), "new_page");
+
+        assert_eq!(testing::get("/files/42").handle(&router).text(), "id");
+        let res = testing::get("/files/readme").handle(&router);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.text(), "path");
+
+        assert_eq!(testing::get("/pages/1").handle(&router).text(), "page");
+        assert_eq!(testing::get("/pages/about").handle(&router).text(), "about");
+        let res = testing::get("/pages/contact").handle(&router);
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.header("allow"), Some("POST"));
+        let res = testing::delete("/files/readme").handle(&router);
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let allow: Vec<&str> = res.headers().get_all(headers::ALLOW).iter().map(|v| v.to_str().unwrap()).collect();
+        assert_eq!(allow, vec!["GET", "HEAD"]);
+    }
+
+    #[test]
+    fn test_same_glob_last_wins() {
+        use iron::testing;
+
+        let mut router = Router::new();
+        router.get("/item", body("first"), "first");
+        router.get("/item", body("second"), "second");
+        assert_eq!(testing::get("/item").handle(&router).text(), "second");
+    }
+
+    #[test]
+    fn test_method_not_allowed() {
+        use iron::testing;
+        use super::MethodNotAllowed;
+
+        let mut router = Router::new();
+        router.get("/item", body(""), "get");
+        router.route("PURGE".parse().unwrap(), "/item", body(""), "purge");
+        router.post("/other", body(""), "post");
+
+        let res = testing::delete("/item").handle(&router);
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let allow: Vec<&str> = res.headers().get_all(headers::ALLOW).iter().map(|v| v.to_str().unwrap()).collect();
+        assert_eq!(allow, vec!["GET", "HEAD", "PURGE"]);
+        let err = res.error().unwrap();
+        assert_eq!(err.to_string(), "Method not allowed, expected one of: GET, HEAD, PURGE");
+
+        let res = testing::head("/other").handle(&router);
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.header("allow"), Some("POST"));
+
+        let res = testing::get("/missing").handle(&router);
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let err = router.no_route("/item");
+        assert_eq!(*err.error.downcast::<MethodNotAllowed>().unwrap(),
+                   MethodNotAllowed(vec![Method::GET, Method::HEAD, "PURGE".parse().unwrap()]));
+    }
+
+    #[test]
+    fn test_allowed_methods_any() {
+        let mut router = Router::new();
+        router.any("/item", body(""), "item");
+        router.route("PURGE".parse().unwrap(), "/item", body(""), "purge");
+        router.post("/other", body(""), "other");
+
+        let resp = router.handle_options("/item");
+        let allow: Vec<&str> = resp.headers.get_all(headers::ALLOW).iter().map(|v| v.to_str().unwrap()).collect();
+        assert_eq!(allow, vec!["GET", "POST", "PUT", "DELETE", "HEAD", "PATCH", "PURGE"]);
+
+        let resp = router.handle_options("/other");
+        let allow: Vec<&str> = resp.headers.get_all(headers::ALLOW).iter().map(|v| v.to_str().unwrap()).collect();
+        assert_eq!(allow, vec!["POST"]);
+    }
+
+    #[test]
+    fn test_handle_options_custom_method() {
+        let mut router = Router::new();
+        router.route("PURGE".parse().unwrap(), "/", body(""), "");
+        let resp = router.handle_options("/");
+        let allow: Vec<&str> = resp.headers.get_all(headers::ALLOW).iter().map(|v| v.to_str().unwrap()).collect();
+        assert_eq!(allow, vec!["PURGE"]);
+    }
+
+    #[test]
+    fn test_trailing_slash_policy() {
+        use iron::testing;
+        use super::TrailingSlashPolicy;
+
+        let mut router = Router::new();
+        router.get("/users/", body("users"), "users");
+        router.post("/items", body("items"), "items");
+
+        let res = testing::get("/users?page=2").handle(&router);
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.header("location"), Some("http://localhost/users/?page=2"));
+
+        router.trailing_slash(TrailingSlashPolicy::PermanentRedirect);
+        let res = testing::post("/items/").handle(&router);
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(res.header("location"), Some("http://localhost/items"));
+
+        router.trailing_slash(TrailingSlashPolicy::Match);
+        assert_eq!(testing::get("/users").handle(&router).text(), "users");
+        assert_eq!(testing::post("/items/").handle(&router).text(), "items");
+
+        router.trailing_slash(TrailingSlashPolicy::Strict);
+        assert_eq!(testing::get("/users").handle(&router).status(), StatusCode::NOT_FOUND);
+        assert_eq!(testing::post("/items/").handle(&router).status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_trailing_slash_under_mount() {
+        use iron::request::OriginalUrl;
+        use iron::testing::{self, TestResponse};
+
+        let mut router = Router::new();
+        router.get("/users/", body(""), "users");
+
+        // As if the router was mounted on `/api`.
+        let mut req = testing::get("/users?page=2").build();
+        req.extensions.insert::<OriginalUrl>("http://localhost/api/users?page=2".parse().unwrap());
+        let res = TestResponse::from_response(router.handle(&mut req).unwrap_err().response, &Method::GET);
+        assert_eq!(res.header("location"), Some("http://localhost/api/users/?page=2"));
+    }
 }
@@ -0,0 +1,285 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use iron::Handler;
+use regex::Regex;
+
+use recognizer::Router as Recognizer;
+use recognizer::{Match, Params};
+
+/// The routes of a single method, matched by their globs and constraints.
+pub struct Routes {
+    // Recognizes the best ranked shape of the globs matching a path, with
+    // positional parameter names.
+    recognizer: Recognizer<String>,
+    // Every shape on its own, best ranked first, for the paths whose best
+    // shape has no route with matching constraints.
+    shapes: Vec<(Rank, String, Recognizer<()>)>,
+    // The routes sharing each shape, in the order they were added.
+    candidates: HashMap<String, Vec<Candidate>>
+}
+
+// Ranks shapes like the recognizer does: fewer wildcards first, then fewer
+// parameters, then more static segments.
+type Rank = (usize, usize, Reverse<usize>);
+
+struct Candidate {
+    glob: String,
+    params: Vec<(String, Option<Constraint>)>,
+    handler: Box<dyn Handler>
+}
+
+impl Routes {
+    pub fn new() -> Routes {
+        Routes {
+            recognizer: Recognizer::new(),
+            shapes: vec![],
+            candidates: HashMap::new()
+        }
+    }
+
+    // Adding a glob again replaces its handler.
+    pub fn add(&mut self, glob: &str, handler: Box<dyn Handler>) {
+        let (shape, params) = parse_glob(glob);
+        if !self.candidates.contains_key(&shape) {
+            self.recognizer.add(&shape, shape.clone());
+            let mut recognizer = Recognizer::new();
+            recognizer.add(&shape, ());
+            self.shapes.push((rank(&shape), shape.clone(), recognizer));
+            // The sort is stable, so shapes of the same rank stay in the
+            // order they were added.
+            self.shapes.sort_by_key(|&(rank, _, _)| rank);
+        }
+
+        let candidates = self.candidates.entry(shape).or_default();
+        match candidates.iter_mut().find(|candidate| candidate.glob == glob) {
+            Some(candidate) => candidate.handler = handler,
+            None => candidates.push(Candidate {
+                glob: glob.to_owned(),
+                params,
+                handler
+            })
+        }
+    }
+
+    // Globs of the same shape are tried in the order they were added, and
+    // the first one whose constraints all match is used. If none does, the
+    // other shapes matching the path are tried, best ranked first.
+    //
+    // The fallback matches the path against every other shape in turn, so it
+    // is linear in the number of shapes. It only happens when constraints
+    // reject the best shape, which is rare for well-ordered routes.
+    pub fn recognize(&self, path: &str) -> Option<Match<&dyn Handler>> {
+        let matched = self.recognizer.recognize(path).ok()?;
+        if let Some(found) = self.select(matched.handler, &matched.params) {
+            return Some(found);
+        }
+
+        self.shapes.iter()
+            .filter(|(_, shape, _)| shape != matched.handler)
+            .filter_map(|(_, shape, recognizer)| {
+                let matched = recognizer.recognize(path).ok()?;
+                self.select(shape, &matched.params)
+            })
+            .next()
+    }
+
+    // The first route of `shape` whose constraints match the positional
+    // parameters of a path.
+    fn select(&self, shape: &str, positional: &Params) -> Option<Match<&dyn Handler>> {
+        'candidates: for candidate in &self.candidates[shape] {
+            let mut params = Params::new();
+            for (i, (name, constraint)) in candidate.params.iter().enumerate() {
+                let value = &positional[&*i.to_string()];
+                if let Some(constraint) = constraint {
+                    if !constraint.matches(value) {
+                        continue 'candidates;
+                    }
+                }
+                params.insert(name.clone(), value.to_owned());
+            }
+            return Some(Match::new(&*candidate.handler, params));
+        }
+        None
+    }
+}
+
+fn rank(shape: &str) -> Rank {
+    let segments = shape.trim_start_matches('/').split('/');
+    let (mut stars, mut dynamics, mut statics) = (0, 0, 0);
+    for segment in segments {
+        if segment.starts_with('*') {
+            stars += 1;
+        } else if segment.starts_with(':') {
+            dynamics += 1;
+        } else {
+            statics += 1;
+        }
+    }
+    (stars, dynamics, Reverse(statics))
+}
+
+/// A constraint on the value of a path parameter, such as `:id<int>`.
+enum Constraint {
+    Int,
+    Uuid,
+    Regex(Regex)
+}
+
+impl Constraint {
+    fn parse(spec: &str) -> Constraint {
+        match spec {
+            "int" => Constraint::Int,
+            "uuid" => Constraint::Uuid,
+            _ => match Regex::new(&format!("^(?:{})$", spec)) {
+                Ok(regex) => Constraint::Regex(regex),
+                Err(e) => panic!("Invalid parameter constraint <{}>: {}", spec, e)
+            }
+        }
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        match *self {
+            Constraint::Int => {
+                let digits = value.strip_prefix('-').unwrap_or(value);
+                !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+            }
+            Constraint::Uuid => {
+                value.len() == 36 && value.char_indices().all(|(i, c)| match i {
+                    8 | 13 | 18 | 23 => c == '-',
+                    _ => c.is_ascii_hexdigit()
+                })
+            }
+            Constraint::Regex(ref regex) => regex.is_match(value)
+        }
+    }
+}
+
+/// Split a parameter segment such as `:id<int>` into its name and
+/// constraint.
+pub fn split_param(segment: &str) -> (&str, Option<&str>) {
+    match segment.find('<') {
+        Some(start) if segment.ends_with('>') => {
+            (&segment[..start], Some(&segment[start + 1..segment.len() - 1]))
+        }
+        _ => (segment, None)
+    }
+}
+
+// Turn a glob into its shape, with positional parameter names and without
+// constraints, and its named and constrained parameters.
+//
+// Panics if a constraint is invalid.
+fn parse_glob(glob: &str) -> (String, Vec<(String, Option<Constraint>)>) {
+    let mut params = vec![];
+    let shape = glob.split('/').map(|segment| {
+        if segment.starts_with(':') || segment.starts_with('*') {
+            // Segments are split first, so a `/` inside a constraint ends it.
+            if segment.contains('<') && !segment.ends_with('>') {
+                panic!("Unterminated parameter constraint in {}, constraints can't contain '/'", glob);
+            }
+            let (name, constraint) = split_param(&segment[1..]);
+            let positional = format!("{}{}", &segment[..1], params.len());
+            params.push((name.to_owned(), constraint.map(Constraint::parse)));
+            positional
+        } else {
+            segment.to_owned()
+        }
+    }).collect::<Vec<_>>().join("/");
+    (shape, params)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Constraint, Routes};
+
+    use iron::prelude::*;
+    use iron::testing::{self, TestResponse};
+    use iron::{Handler, Method};
+    use recognizer::Match;
+
+    fn handler(body: &'static str) -> Box<dyn Handler> {
+        Box::new(move |_: &mut Request| Ok(Response::with(body)))
+    }
+
+    fn body(matched: Match<&dyn Handler>) -> String {
+        let res = matched.handler.handle(&mut testing::get("/").build()).unwrap();
+        TestResponse::from_response(res, &Method::GET).text().to_owned()
+    }
+
+    #[test]
+    fn test_constraints() {
+        assert!(Constraint::parse("int").matches("-42"));
+        assert!(!Constraint::parse("int").matches("4x2"));
+        assert!(!Constraint::parse("int").matches("-"));
+        assert!(Constraint::parse("uuid").matches("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(!Constraint::parse("uuid").matches("67e55044-10b1-426f-9247"));
+        assert!(Constraint::parse("[a-z-]+").matches("hello-world"));
+        assert!(!Constraint::parse("[a-z-]+").matches("Hello"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid parameter constraint <[a-z>")]
+    fn test_invalid_constraint() {
+        Constraint::parse("[a-z");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unterminated parameter constraint in /files/:path<[a-z/]+>")]
+    fn test_constraint_with_slash() {
+        Routes::new().add("/files/:path<[a-z/]+>", handler("path"));
+    }
+
+    #[test]
+    fn test_constrained_routes() {
+        let mut routes = Routes::new();
+        routes.add("/users/:id<int>", handler("id"));
+        routes.add("/users/:slug<[a-z-]+>", handler("slug"));
+        routes.add("/users/:id<int>/files/*path", handler("files"));
+
+        let matched = routes.recognize("/users/42").unwrap();
+        assert_eq!(matched.params.find("id"), Some("42"));
+        assert_eq!(matched.params.find("slug"), None);
+
+        let matched = routes.recognize("/users/iron-man").unwrap();
+        assert_eq!(matched.params.find("slug"), Some("iron-man"));
+
+        let matched = routes.recognize("/users/7/files/a/b").unwrap();
+        assert_eq!(matched.params.find("id"), Some("7"));
+        assert_eq!(matched.params.find("path"), Some("a/b"));
+
+        assert!(routes.recognize("/users/Iron").is_none());
+        assert!(routes.recognize("/users/x/files/a").is_none());
+    }
+
+    #[test]
+    fn test_constrained_fallback() {
+        let mut routes = Routes::new();
+        routes.add("/files/:id<int>", handler("id"));
+        routes.add("/files/*path", handler("path"));
+        routes.add("/files/:id<int>/raw", handler("raw"));
+        routes.add("/files/*path/raw", handler("path raw"));
+
+        let matched = routes.recognize("/files/42").unwrap();
+        assert_eq!(matched.params.find("id"), Some("42"));
+
+        let matched = routes.recognize("/files/readme").unwrap();
+        assert_eq!(matched.params.find("path"), Some("readme"));
+        assert_eq!(matched.params.find("id"), None);
+
+        let matched = routes.recognize("/files/readme/raw").unwrap();
+        assert_eq!(matched.params.find("path"), Some("readme"));
+    }
+
+    #[test]
+    fn test_same_glob_replaces_handler() {
+        let mut routes = Routes::new();
+        routes.add("/users/:id<int>", handler("first"));
+        routes.add("/users/:id<int>", handler("second"));
+        routes.add("/users/:name", handler("name"));
+        routes.add("/users/:name", handler("last name"));
+
+        assert_eq!(body(routes.recognize("/users/1").unwrap()), "second");
+        assert_eq!(body(routes.recognize("/users/bob").unwrap()), "last name");
+    }
+}
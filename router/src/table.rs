@@ -0,0 +1,223 @@
+use std::error::Error;
+use std::fmt::{self, Write};
+
+use iron::{Handler, IronError, IronResult, Request, Response};
+#[cfg(feature = "serde_json")]
+use iron::headers;
+use iron::{method, mime, StatusCode};
+#[cfg(feature = "serde_json")]
+use serde_json::{self, Value};
+
+use router::RouterInner;
+
+/// A route registered on a `Router`, as listed by `Router::routes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteInfo {
+    /// The method of the route, or `None` for routes added with `any`.
+    pub method: Option<method::Method>,
+    /// The glob of the route, including the prefixes of its groups.
+    pub glob: String,
+    /// The route id, used by `url_for`.
+    pub route_id: String,
+    /// A description of the route, set with `Router::describe`.
+    pub description: Option<String>,
+    /// The tags of the route, added with `Router::tag`.
+    pub tags: Vec<String>
+}
+
+impl RouteInfo {
+    pub(crate) fn new(method: Option<method::Method>, glob: &str, route_id: &str) -> RouteInfo {
+        RouteInfo {
+            method,
+            glob: glob.to_owned(),
+            route_id: route_id.to_owned(),
+            description: None,
+            tags: vec![]
+        }
+    }
+
+    fn method_name(&self) -> &str {
+        self.method.as_ref().map_or("*", |method| method.as_str())
+    }
+
+    #[cfg(feature = "serde_json")]
+    fn to_json(&self) -> Value {
+        json!({
+            "method": self.method_name(),
+            "glob": self.glob,
+            "route_id": self.route_id,
+            "description": self.description,
+            "tags": self.tags
+        })
+    }
+}
+
+/// A `Handler` listing the routes of the `Router` it is routed by, for
+/// debugging and generating documentation.
+///
+/// The table is rendered as text. With the `serde_json` feature, it is
+/// rendered as JSON instead if the request accepts `application/json` or has
+/// a `format=json` query parameter:
+///
+/// ```
+/// # use router::{RouteTable, Router};
+/// let mut router = Router::new();
+/// router.get("/_routes", RouteTable, "routes");
+/// ```
+///
+/// Routes added with `any` have the method `*`.
+///
+/// Requests which weren't routed by a `Router` fail with `NotRouted`.
+#[derive(Debug, Clone, Copy)]
+pub struct RouteTable;
+
+/// The error thrown by a `RouteTable` handling a request which wasn't
+/// routed by a `Router`, so it has no routes to list. It is always
+/// accompanied by an Internal Server Error response.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NotRouted;
+
+impl fmt::Display for NotRouted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("The route table must be routed to by a Router.")
+    }
+}
+
+impl Error for NotRouted {
+    fn description(&self) -> &str { "Not Routed" }
+}
+
+impl Handler for RouteTable {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let inner = match req.extensions.get::<RouterInner>() {
+            Some(inner) => inner.clone(),
+            None => return Err(IronError::new(NotRouted, StatusCode::INTERNAL_SERVER_ERROR))
+        };
+
+        #[cfg(feature = "serde_json")]
+        {
+            if wants_json(req) {
+                let routes: Vec<Value> = inner.routes.iter().map(RouteInfo::to_json).collect();
+                let body = serde_json::to_string_pretty(&routes).unwrap();
+                return Ok(Response::with((StatusCode::OK, mime::APPLICATION_JSON, body)));
+            }
+        }
+        Ok(Response::with((StatusCode::OK, mime::TEXT_PLAIN_UTF_8, render_text(&inner.routes))))
+    }
+}
+
+#[cfg(feature = "serde_json")]
+fn wants_json(req: &Request) -> bool {
+    let accept = req.headers.get(headers::ACCEPT).and_then(|value| value.to_str().ok());
+    accept.map_or(false, |accept| accept.contains("application/json")) ||
+        req.url.as_ref().query_pairs().any(|(name, value)| name == "format" && value == "json")
+}
+
+// One route per line, with aligned method, glob and route id columns.
+fn render_text(routes: &[RouteInfo]) -> String {
+    let method_width = routes.iter().map(|route| route.method_name().len()).max().unwrap_or(0);
+    let glob_width = routes.iter().map(|route| route.glob.len()).max().unwrap_or(0);
+    let id_width = routes.iter().map(|route| route.route_id.len()).max().unwrap_or(0);
+
+    let mut text = String::new();
+    for route in routes {
+        let mut line = format!("{:m$}  {:g$}  {:i$}", route.method_name(), route.glob, route.route_id,
+                               m = method_width, g = glob_width, i = id_width);
+        if let Some(ref description) = route.description {
+            write!(line, "  {}", description).unwrap();
+        }
+        if !route.tags.is_empty() {
+            write!(line, "  [{}]", route.tags.join(", ")).unwrap();
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use iron::prelude::*;
+    use iron::testing;
+    use iron::{Method, StatusCode};
+
+    use super::RouteInfo;
+    use {NotRouted, RouteTable, Router};
+
+    fn router() -> Router {
+        let ok = |_: &mut Request| Ok(Response::with(StatusCode::OK));
+        let mut router = Router::new();
+        router.get("/users/:id<int>", ok, "user");
+        router.put("/users/:id<int>", ok, "user");
+        router.group("/admin", |g| { g.any("/*path", ok, "admin"); });
+        router.get("/_routes", RouteTable, "routes");
+        router.describe("user", "A single user").tag("user", "users").tag("user", "public");
+        router
+    }
+
+    #[test]
+    fn test_routes() {
+        let router = router();
+        let user = RouteInfo {
+            method: Some(Method::GET),
+            glob: "/users/:id<int>".to_owned(),
+            route_id: "user".to_owned(),
+            description: Some("A single user".to_owned()),
+            tags: vec!["users".to_owned(), "public".to_owned()]
+        };
+        assert_eq!(router.routes()[0], user);
+        assert_eq!(router.routes()[1].method, Some(Method::PUT));
+        assert_eq!(router.routes()[1].tags, user.tags);
+        assert_eq!(router.routes()[2], RouteInfo::new(None, "/admin/*path", "admin"));
+        assert_eq!(router.routes().len(), 4);
+    }
+
+    #[test]
+    fn test_route_table() {
+        let router = router();
+
+        let res = testing::get("/_routes").handle(&router);
+        assert_eq!(res.header("content-type"), Some("text/plain; charset=utf-8"));
+        assert_eq!(res.text(), "\
+GET  /users/:id<int>  user    A single user  [users, public]
+PUT  /users/:id<int>  user    A single user  [users, public]
+*    /admin/*path     admin
+GET  /_routes         routes
+");
+    }
+
+    #[test]
+    fn test_route_table_not_routed() {
+        let res = testing::get("/_routes").handle(&RouteTable);
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.error().unwrap().to_string(), NotRouted.to_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn test_route_table_json() {
+        let router = router();
+
+        let res = testing::get("/_routes?format=json").handle(&router);
+        assert_eq!(res.header("content-type"), Some("application/json"));
+        let routes: Vec<::serde_json::Value> = res.json().unwrap();
+        assert_eq!(routes[0], json!({
+            "method": "GET",
+            "glob": "/users/:id<int>",
+            "route_id": "user",
+            "description": "A single user",
+            "tags": ["users", "public"]
+        }));
+        assert_eq!(routes[2]["method"], "*");
+        assert_eq!(routes[2]["description"], ::serde_json::Value::Null);
+
+        let res = testing::get("/_routes").header("accept", "application/json").handle(&router);
+        assert_eq!(res.json::<Vec<::serde_json::Value>>().unwrap().len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "No route with that ID: missing")]
+    fn test_describe_unknown_route() {
+        Router::new().describe("missing", "?");
+    }
+}
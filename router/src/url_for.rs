@@ -1,9 +1,13 @@
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 
 use url::Url;
 
 use iron::prelude::*;
+use iron::request::OriginalUrl;
 use router::RouterInner;
+use routes::split_param;
 
 /// Generate a URL based off of the currently requested URL.
 ///
@@ -11,25 +15,76 @@ use router::RouterInner;
 ///
 /// `params` will be inserted as route parameters if fitting, the rest will be appended as query
 /// parameters.
+///
+/// Panics if the URL can't be generated, see `try_url_for`.
 pub fn url_for(request: &Request, route_id: &str, params: HashMap<String, String>) -> ::iron::Url {
-    let inner = request.extensions.get::<RouterInner>().expect("Couldn\'t find router set up properly.");
-    let glob = inner.route_ids.get(route_id).expect("No route with that ID");
+    try_url_for(request, route_id, params).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Generate an absolute URL like `url_for`, or fail if the request wasn't
+/// routed by a `Router`, there is no route `route_id` or a parameter of the
+/// route is missing from `params`.
+///
+/// Under a `mount::Mount`, the path the router is mounted on is kept, so
+/// that the URL points to the route from outside of the mount.
+pub fn try_url_for(request: &Request, route_id: &str, params: HashMap<String, String>) -> Result<::iron::Url, UrlForError> {
+    let inner = request.extensions.get::<RouterInner>().ok_or(UrlForError::NoRouter)?;
+    let glob = inner.route_ids.get(route_id)
+        .ok_or_else(|| UrlForError::UnknownRoute(route_id.to_owned()))?;
+
+    let mut url = match request.extensions.get::<OriginalUrl>() {
+        Some(original) => original.clone(),
+        None => request.url.clone()
+    };
+    let prefix = mount_prefix(request);
+    url_for_impl(url.as_mut(), glob, params).map_err(|name| UrlForError::MissingParam {
+        route_id: route_id.to_owned(),
+        name
+    })?;
+
+    if !prefix.is_empty() {
+        let path = format!("{}{}", prefix, url.as_ref().path());
+        url.as_mut().set_path(&path);
+    }
+    Ok(url)
+}
+
+/// Generate the path and query of a URL like `try_url_for`, without the
+/// scheme and host, e.g. for links within the same site.
+pub fn try_path_for(request: &Request, route_id: &str, params: HashMap<String, String>) -> Result<String, UrlForError> {
+    let url = try_url_for(request, route_id, params)?;
+    let url = url.as_ref();
+    Ok(match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned()
+    })
+}
 
-    let mut url = request.url.clone();
-    url_for_impl(url.as_mut(), glob, params);
-    url
+// The path the router is mounted on, without a trailing slash, or an empty
+// path if it isn't mounted.
+fn mount_prefix(request: &Request) -> &str {
+    let original = match request.extensions.get::<OriginalUrl>() {
+        Some(original) => original.as_ref().path(),
+        None => return ""
+    };
+    // Mounts strip their path from the start of the original path, leaving
+    // `/` for requests of the mounted path itself, with or without a
+    // trailing slash.
+    let path = request.url.as_ref().path();
+    original.strip_suffix(path).unwrap_or(original).trim_end_matches('/')
 }
 
-fn url_for_impl(url: &mut Url, glob: &str, mut params: HashMap<String, String>) {
+// Fails with the name of the first missing parameter.
+fn url_for_impl(url: &mut Url, glob: &str, mut params: HashMap<String, String>) -> Result<(), String> {
     {
         let mut url_path_segments = url.path_segments_mut().unwrap();
         url_path_segments.clear();
         for path_segment in glob.split('/') {
             if path_segment.len() > 1 && (path_segment.starts_with(':') || path_segment.starts_with('*')) {
-                let key = &path_segment[1..];
+                let (key, _) = split_param(&path_segment[1..]);
                 match params.remove(key) {
                     Some(x) => url_path_segments.push(&x),
-                    None => panic!("No value for key {}", key)
+                    None => return Err(key.to_owned())
                 };
             } else {
                 url_path_segments.push(path_segment);
@@ -45,13 +100,50 @@ fn url_for_impl(url: &mut Url, glob: &str, mut params: HashMap<String, String>)
     }
 
     url.set_fragment(None);
+    Ok(())
 }
 
+/// The error returned when a URL can't be generated by `try_url_for`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UrlForError {
+    /// The request wasn't routed by a `Router`.
+    NoRouter,
+    /// There is no route with this id.
+    UnknownRoute(String),
+    /// A parameter of the route is missing.
+    MissingParam {
+        /// The id of the route.
+        route_id: String,
+        /// The name of the parameter.
+        name: String
+    }
+}
+
+impl fmt::Display for UrlForError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UrlForError::NoRouter => f.write_str("Couldn't find router set up properly."),
+            UrlForError::UnknownRoute(ref route_id) => write!(f, "No route with that ID: {}", route_id),
+            UrlForError::MissingParam { ref route_id, ref name } =>
+                write!(f, "No value for key {} of route {}", name, route_id)
+        }
+    }
+}
+
+impl Error for UrlForError {}
+
 #[cfg(test)]
 mod test {
-    use super::url_for_impl;
+    use super::{try_path_for, try_url_for, url_for_impl, UrlForError};
     use std::collections::HashMap;
 
+    use iron::prelude::*;
+    use iron::request::OriginalUrl;
+    use iron::testing::{self, TestResponse};
+    use iron::{Handler, Method, StatusCode};
+
+    use Router;
+
     #[test]
     fn test_no_trailing_slash() {
         let mut url = "http://localhost/foo/bar/baz".parse().unwrap();
@@ -59,7 +151,7 @@ mod test {
             let mut rv = HashMap::new();
             rv.insert("user".into(), "bam".into());
             rv
-        });
+        }).unwrap();
         assert_eq!(url.to_string(), "http://localhost/foo/bam");
     }
 
@@ -70,7 +162,65 @@ mod test {
             let mut rv = HashMap::new();
             rv.insert("user".into(), "bam".into());
             rv
-        });
+        }).unwrap();
         assert_eq!(url.to_string(), "http://localhost/foo/bam/");
     }
+
+    #[test]
+    fn test_constrained_param() {
+        let mut url = "http://localhost/".parse().unwrap();
+        url_for_impl(&mut url, "/users/:id<int>", {
+            let mut rv = HashMap::new();
+            rv.insert("id".into(), "7".into());
+            rv
+        }).unwrap();
+        assert_eq!(url.to_string(), "http://localhost/users/7");
+    }
+
+    #[test]
+    fn test_missing_param() {
+        let mut url = "http://localhost/".parse().unwrap();
+        assert_eq!(url_for_impl(&mut url, "/users/:id", HashMap::new()), Err("id".to_owned()));
+    }
+
+    fn router() -> Router {
+        let mut router = Router::new();
+        router.get("/", |req: &mut Request| {
+            let params = vec![("id".to_owned(), "7".to_owned()), ("tab".to_owned(), "files".to_owned())];
+            let url = try_url_for(req, "user", params.into_iter().collect()).unwrap();
+            let path = try_path_for(req, "user", vec![("id".to_owned(), "7".to_owned())].into_iter().collect()).unwrap();
+            Ok(Response::with((StatusCode::OK, format!("{} {}", url, path))))
+        }, "index");
+        router.get("/users/:id/", |req: &mut Request| {
+            let err = try_url_for(req, "user", HashMap::new()).unwrap_err();
+            assert_eq!(err, UrlForError::MissingParam { route_id: "user".to_owned(), name: "id".to_owned() });
+            let err = try_url_for(req, "missing", HashMap::new()).unwrap_err();
+            Ok(Response::with((StatusCode::OK, err.to_string())))
+        }, "user");
+        router
+    }
+
+    #[test]
+    fn test_try_url_for() {
+        let router = router();
+        assert_eq!(testing::get("/").handle(&router).text(),
+                   "http://localhost/users/7/?tab=files /users/7/");
+        assert_eq!(testing::get("/users/7/").handle(&router).text(), "No route with that ID: missing");
+
+        let req = testing::get("/").build();
+        assert_eq!(try_url_for(&req, "user", HashMap::new()), Err(UrlForError::NoRouter));
+    }
+
+    #[test]
+    fn test_try_url_for_under_mount() {
+        // As if the router was mounted on `/api`.
+        let router = router();
+        for original in &["http://localhost/api", "http://localhost/api/"] {
+            let mut req = testing::get("/").build();
+            req.extensions.insert::<OriginalUrl>(original.parse().unwrap());
+            let res = router.handle(&mut req).unwrap();
+            assert_eq!(TestResponse::from_response(res, &Method::GET).text(),
+                       "http://localhost/api/users/7/?tab=files /api/users/7/");
+        }
+    }
 }